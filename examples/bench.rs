@@ -0,0 +1,128 @@
+// Measure how many requests per second a running server answers
+//
+// cargo r -r --example bench -- [ADDRESS] [PATH] [CLIENTS] [IDLE] [SECONDS]
+//
+// Each client sends requests one after another on a persistent connection, while the idle connections send
+// half a request and stall, which ties up a worker of the thread pool but costs the event loop almost nothing.
+
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+
+fn main()
+{
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let address = args.first().map_or("localhost:8080", String::as_str);
+	let path = args.get(1).map_or("/", String::as_str);
+	let clients: usize = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(64);
+	let idle: usize = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(0);
+	let seconds: u64 = args.get(4).and_then(|arg| arg.parse().ok()).unwrap_or(5);
+
+	// Stall some connections in the middle of a request
+	let mut stalled = Vec::new();
+	for _ in 0..idle {
+		match TcpStream::connect(address) {
+			Ok(mut stream) => {
+				let _ = stream.write_all(b"GET / HTTP/1.1\r\n");
+				stalled.push(stream);
+			},
+			Err(error) => {
+				eprintln!("bench: failed to connect to {address}: {error}");
+				std::process::exit(1);
+			},
+		}
+	}
+
+	let request = format!("GET {path} HTTP/1.1\r\nHost: {address}\r\n\r\n");
+	let stop = AtomicBool::new(false);
+	let completed = AtomicU64::new(0);
+	let failed = AtomicU64::new(0);
+
+	let start = Instant::now();
+	std::thread::scope(|scope| {
+		for _ in 0..clients {
+			scope.spawn(|| {
+				while !stop.load(Ordering::Relaxed) {
+					match run_client(address, request.as_bytes(), &stop, &completed) {
+						Ok(()) => (),
+						Err(_) => { failed.fetch_add(1, Ordering::Relaxed); },
+					}
+				}
+			});
+		}
+		std::thread::sleep(Duration::from_secs(seconds));
+		stop.store(true, Ordering::Relaxed);
+	});
+	let elapsed = start.elapsed().as_secs_f64();
+
+	let completed = completed.load(Ordering::Relaxed);
+	println!("{clients} clients, {idle} idle connections, {seconds} seconds");
+	println!("{completed} responses, {} failed connections", failed.load(Ordering::Relaxed));
+	println!("{:.0} requests per second", completed as f64 / elapsed);
+	drop(stalled);
+}
+
+
+// Send requests on one connection until told to stop
+fn run_client(address: &str, request: &[u8], stop: &AtomicBool, completed: &AtomicU64) -> std::io::Result<()>
+{
+	let mut stream = TcpStream::connect(address)?;
+	stream.set_nodelay(true)?;
+	stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+
+	let mut line = String::new();
+	let mut answered = false;
+	while !stop.load(Ordering::Relaxed) {
+		// A server may close a connection after a response without saying so, like one that doesn't keep connections
+		// open, which fails the next request before any of its response arrives
+		if let Err(error) = stream.write_all(request) {
+			return if answered { Ok(()) } else { Err(error) };
+		}
+
+		// Read the head, keeping the body length and whether the server closes the connection
+		let mut content_length = 0;
+		let mut close = false;
+		let mut started = false;
+		loop {
+			line.clear();
+			match reader.read_line(&mut line) {
+				Ok(0) | Err(_) if answered && !started => return Ok(()),
+				Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+				Ok(_) => (),
+				Err(error) => return Err(error),
+			}
+			started = true;
+			let line = line.trim_end();
+			if line.is_empty() {
+				break;
+			}
+			let Some((name, value)) = line.split_once(':') else {
+				continue;
+			};
+			if name.eq_ignore_ascii_case("content-length") {
+				content_length = value.trim().parse().unwrap_or(0);
+			} else if name.eq_ignore_ascii_case("connection") && value.trim().eq_ignore_ascii_case("close") {
+				close = true;
+			}
+		}
+
+		// Skip the body
+		std::io::copy(&mut (&mut reader).take(content_length), &mut std::io::sink())?;
+		completed.fetch_add(1, Ordering::Relaxed);
+		answered = true;
+		if close {
+			return Ok(());
+		}
+	}
+	return Ok(());
+}
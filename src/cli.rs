@@ -0,0 +1,134 @@
+use std::path::Path;
+
+
+// What the program was asked to do
+pub enum Command
+{
+	Serve(Args),
+	Help,
+	Version,
+}
+
+
+// Values from the command line that override the defaults
+#[derive(Default)]
+pub struct Args
+{
+	pub root: Option<String>,
+	pub port: Option<u16>,
+	pub host: Option<String>,
+	// A path to explain the routing of, instead of serving
+	pub explain_route: Option<String>,
+}
+
+
+pub const USAGE: &str = "\
+Usage: serve [OPTIONS] [DIR]
+
+Serve the files in DIR over HTTP. Without DIR, serve \"public\" if it exists,
+otherwise the current directory.
+
+Options:
+  -p, --port <PORT>  Port to listen on, or 0 for any free port (default: 8080,
+                     or the next free port after it)
+      --host <ADDR>  Host name or IP address to listen on (default: localhost)
+      --explain-route <PATH>
+                     Show which rewrite rules match a path and how it would be
+                     answered, then exit
+  -h, --help         Print this help and exit
+  -V, --version      Print the version and exit";
+
+
+// Parse the command line arguments, not including the program name
+pub fn parse(arguments: impl Iterator<Item = String>) -> Result<Command, String>
+{
+	let mut args = Args::default();
+	let mut arguments = arguments.peekable();
+	let mut only_positional = false;
+
+	while let Some(argument) = arguments.next() {
+		// Treat everything after "--" as positional
+		if only_positional || !argument.starts_with('-') || argument == "-" {
+			set_root(&mut args, argument)?;
+			continue;
+		}
+
+		// Split "--name=value" into its name and value
+		let (name, inline_value) = match argument.split_once('=') {
+			Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+			_ => (argument, None),
+		};
+
+		match name.as_str() {
+			"--" => only_positional = true,
+			"-h" | "--help" => return Ok(Command::Help),
+			"-V" | "--version" => return Ok(Command::Version),
+			"-p" | "--port" => {
+				let value = option_value(&name, inline_value, &mut arguments)?;
+				args.port = Some(parse_port(&value)?);
+			},
+			"--host" => {
+				let value = option_value(&name, inline_value, &mut arguments)?;
+				args.host = Some(parse_host(&value)?);
+			},
+			"--explain-route" => {
+				let value = option_value(&name, inline_value, &mut arguments)?;
+				if !value.starts_with('/') {
+					return Err(format!("invalid path '{value}', expected it to start with '/'"));
+				}
+				args.explain_route = Some(value);
+			},
+			_ => return Err(format!("unknown option '{name}'")),
+		}
+	}
+
+	return Ok(Command::Serve(args));
+}
+
+
+// Get the value of an option either from "--name=value" or the next argument
+fn option_value(name: &str, inline_value: Option<String>, arguments: &mut impl Iterator<Item = String>)
+	-> Result<String, String>
+{
+	if let Some(value) = inline_value {
+		return Ok(value);
+	}
+	return arguments.next().ok_or_else(|| format!("option '{name}' requires a value"));
+}
+
+
+// Accept a single directory that exists
+fn set_root(args: &mut Args, root: String) -> Result<(), String>
+{
+	if args.root.is_some() {
+		return Err(format!("unexpected argument '{root}', only one directory can be served"));
+	}
+	if !Path::new(&root).is_dir() {
+		return Err(format!("'{root}' is not a directory"));
+	}
+	args.root = Some(root);
+	return Ok(());
+}
+
+
+// Parse a TCP port number
+pub fn parse_port(value: &str) -> Result<u16, String>
+{
+	return value.parse::<u16>().map_err(|_| format!("invalid port '{value}', expected a number from 0 to 65535"));
+}
+
+
+// Check that a host looks like a host name or an IP address, without IPv6 brackets
+pub fn parse_host(value: &str) -> Result<String, String>
+{
+	let host = value.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(value);
+
+	let valid = !host.is_empty() && host.bytes().all(|byte| {
+		byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b'_' | b':' | b'%')
+	});
+
+	if !valid {
+		return Err(format!("invalid host '{value}', expected a host name or an IP address"));
+	}
+	return Ok(host.to_string());
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::Metadata;
+use std::io::Read;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::UNIX_EPOCH;
+
+use crate::config::Config;
+use crate::config::EntityTags;
+use crate::date;
+use crate::request::Headers;
+use crate::response::StatusCode;
+
+
+const HASH_READ_SIZE: usize = 65536;
+
+// The largest file to hash for its entity tag, since hashing blocks every connection of the epoll server, so larger files
+// get entity tags from their metadata
+const MAX_HASHED_SIZE: u64 = 16 << 20;
+
+// How many content hashes to remember before forgetting them all
+const MAX_CACHED_HASHES: usize = 4096;
+
+// The content hashes of files by their versions, so each version of a file is only read once
+static HASHES: Mutex<Option<HashMap<Version, u64>>> = Mutex::new(None);
+
+
+// The inode, modification time in nanoseconds, and size of a file
+type Version = (u64, u128, u64);
+
+
+// What identifies the current version of a file, so clients can check whether their copy is still current
+pub struct Validators
+{
+	pub entity_tag: Option<String>,
+	// Seconds since 1970
+	pub last_modified: Option<u64>,
+}
+
+
+impl Validators
+{
+	pub fn new(config: &Config, file: &File, metadata: &Metadata) -> Validators
+	{
+		let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+		let metadata_tag = || modified.map(|modified| format!("\"{:x}-{:x}-{:x}\"", inode(metadata), modified.as_nanos(), metadata.len()));
+		let entity_tag = match config.entity_tags {
+			EntityTags::Hash if metadata.len() <= MAX_HASHED_SIZE => cached_hash(file, metadata).map(|hash| format!("\"{hash:016x}\"")),
+			_ => metadata_tag(),
+		};
+		return Validators { entity_tag, last_modified: modified.map(|modified| modified.as_secs()) };
+	}
+
+
+	pub fn headers(&self) -> Vec<(String, String)>
+	{
+		let mut headers = Vec::new();
+		if let Some(entity_tag) = &self.entity_tag {
+			headers.push((String::from("ETag"), entity_tag.clone()));
+		}
+		if let Some(last_modified) = self.last_modified {
+			headers.push((String::from("Last-Modified"), date::format(last_modified)));
+		}
+		return headers;
+	}
+}
+
+
+// Check the preconditions of a GET or HEAD request in the order of RFC 9110, getting the status code to send instead of the content
+pub fn evaluate(headers: &Headers, validators: &Validators) -> Option<StatusCode>
+{
+	// Only send a version the client knows about
+	if headers.get_all("If-Match").next().is_some() {
+		if !matches(headers, "If-Match", validators, true) {
+			return Some(StatusCode::PreconditionFailed);
+		}
+	} else if let (Some(since), Some(last_modified)) = (headers.get("If-Unmodified-Since").and_then(date::parse), validators.last_modified) {
+		if last_modified > since {
+			return Some(StatusCode::PreconditionFailed);
+		}
+	}
+
+	// Skip the content when the client's copy is current
+	if headers.get_all("If-None-Match").next().is_some() {
+		if matches(headers, "If-None-Match", validators, false) {
+			return Some(StatusCode::NotModified);
+		}
+	} else if let (Some(since), Some(last_modified)) = (headers.get("If-Modified-Since").and_then(date::parse), validators.last_modified) {
+		if last_modified <= since {
+			return Some(StatusCode::NotModified);
+		}
+	}
+
+	return None;
+}
+
+
+// Check whether ranges may be sent, which needs the validator of an If-Range header to match the file
+pub fn if_range(headers: &Headers, validators: &Validators) -> bool
+{
+	if headers.get_all("If-Range").next().is_none() {
+		return true;
+	}
+	let Some(value) = headers.get("If-Range").map(str::trim) else {
+		return false;
+	};
+
+	// An entity tag has to be the same strong tag, and a date has to be exactly the same
+	if value.starts_with('"') || value.starts_with("W/") {
+		return validators.entity_tag.as_deref() == Some(value);
+	}
+	return validators.last_modified.is_some_and(|last_modified| date::format(last_modified) == value);
+}
+
+
+// Check whether a list of entity tags in some header fields has the current one, or is "*"
+fn matches(headers: &Headers, name: &str, validators: &Validators, strong: bool) -> bool
+{
+	let Some(current) = validators.entity_tag.as_deref() else {
+		return false;
+	};
+
+	for value in headers.get_all(name) {
+		if value.trim() == "*" {
+			return true;
+		}
+		for (weak, tag) in entity_tags(value) {
+			if tag == current && !(strong && weak) {
+				return true;
+			}
+		}
+	}
+	return false;
+}
+
+
+// Split a list like `"a", W/"b"` into entity tags with their quotes and whether they're weak, stopping at anything invalid
+fn entity_tags(value: &str) -> Vec<(bool, &str)>
+{
+	let mut tags = Vec::new();
+	let mut rest = value;
+	loop {
+		rest = rest.trim_start_matches([' ', '\t', ',']);
+		if rest.is_empty() {
+			return tags;
+		}
+
+		let weak = rest.starts_with("W/");
+		let tag = if weak { &rest[2..] } else { rest };
+		let Some(length) = tag.strip_prefix('"').and_then(|tag| tag.find('"')).map(|end| end + 2) else {
+			return tags;
+		};
+		tags.push((weak, &tag[..length]));
+		rest = &tag[length..];
+	}
+}
+
+
+// Get the content hash of a file, from the cache when the file hasn't changed since it was hashed
+fn cached_hash(file: &File, metadata: &Metadata) -> Option<u64>
+{
+	// Without inodes, files of the same size and time would share a hash
+	let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+	let Some(key) = modified.filter(|_| cfg!(unix)).map(|modified| (inode(metadata), modified.as_nanos(), metadata.len())) else {
+		return hash(file).ok();
+	};
+
+	if let Some(hash) = HASHES.lock().unwrap_or_else(PoisonError::into_inner).as_ref().and_then(|hashes| hashes.get(&key)) {
+		return Some(*hash);
+	}
+	let hash = hash(file).ok()?;
+	let mut hashes = HASHES.lock().unwrap_or_else(PoisonError::into_inner);
+	let hashes = hashes.get_or_insert_with(HashMap::new);
+	if hashes.len() >= MAX_CACHED_HASHES {
+		hashes.clear();
+	}
+	hashes.insert(key, hash);
+	return Some(hash);
+}
+
+
+// Hash the content of a file with 64-bit FNV-1a
+fn hash(mut file: &File) -> std::io::Result<u64>
+{
+	let mut hash: u64 = 0xcbf29ce484222325;
+	let mut buffer = vec![0; HASH_READ_SIZE];
+	loop {
+		let length = match file.read(&mut buffer) {
+			Ok(0) => return Ok(hash),
+			Ok(length) => length,
+			Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		};
+		for byte in &buffer[..length] {
+			hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+		}
+	}
+}
+
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64
+{
+	use std::os::unix::fs::MetadataExt;
+
+	return metadata.ino();
+}
+
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64
+{
+	return 0;
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use std::fs::File;
+
+	use super::Validators;
+	use crate::config::EntityTags;
+	use crate::testing::TempDir;
+
+
+	#[test]
+	fn hashed_entity_tags()
+	{
+		let directory = TempDir::new("hashed-entity-tags");
+		directory.file("small.txt", "hello");
+		directory.file("copy.txt", "hello");
+		directory.file("large.bin", "");
+		File::options().write(true).open(directory.path.join("large.bin")).unwrap().set_len(super::MAX_HASHED_SIZE + 1).unwrap();
+		let mut config = directory.config();
+		config.entity_tags = EntityTags::Hash;
+
+		let entity_tag = |name: &str| {
+			let file = File::open(directory.path.join(name)).unwrap();
+			let metadata = file.metadata().unwrap();
+			return Validators::new(&config, &file, &metadata).entity_tag.unwrap();
+		};
+		assert_eq!(entity_tag("small.txt"), "\"a430d84680aabd0b\"");
+		assert_eq!(entity_tag("small.txt"), entity_tag("copy.txt"));
+		// Too large to hash, so made from the metadata
+		assert_eq!(entity_tag("large.bin").matches('-').count(), 2);
+	}
+}
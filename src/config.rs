@@ -0,0 +1,714 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cli;
+use crate::regex::Regex;
+use crate::response::StatusCode;
+use crate::toml;
+use crate::toml::Value;
+
+
+// Files searched for in the working directory, in order
+pub const CONFIG_FILES: [&str; 2] = ["serve.toml", ".serve.toml"];
+
+pub const DEFAULT_HOSTNAME: &str = "localhost";
+pub const DEFAULT_PORT: u16 = 8080;
+
+// How many ports to try after the default port is taken
+pub const PORT_ATTEMPTS: u16 = 20;
+
+pub const PREFERRED_PUBLIC_DIR: &str = "public";
+
+// Files served for a directory, the first one that exists winning
+pub const DEFAULT_INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
+
+pub const DEFAULT_WORKERS: usize = 16;
+pub const DEFAULT_MAX_CONNECTIONS: usize = 512;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The charset of text files
+pub const DEFAULT_CHARSET: &str = "utf-8";
+
+// Let browsers keep files but check whether they changed before using them
+pub const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+pub const DEFAULT_FINGERPRINTED_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+
+// Everything that can be customized, after layering the defaults, the config file, the environment, and the arguments
+pub struct Config
+{
+	pub host: String,
+	pub port: u16,
+	pub port_fallback: bool,
+	pub root: Option<String>,
+	// The public directory with every symbolic link resolved, which requests can't leave
+	pub canonical_root: PathBuf,
+	pub follow_symlinks: Symlinks,
+	pub index_files: Vec<String>,
+	pub trailing_slash: TrailingSlash,
+	// Whether /about is served from about.html
+	pub clean_urls: bool,
+	// Whether /about.html redirects to /about with clean URLs
+	pub redirect_html: bool,
+	// Whether to list a directory without an index file
+	pub listing: bool,
+	// The document of a single-page application, served for client-side routes that aren't files
+	pub spa_fallback: Option<String>,
+	// Whether error pages show the file that was looked for
+	pub dev: bool,
+	// Globs of more paths to answer with 404, on top of dotfiles and editor files
+	pub deny: Vec<String>,
+	// Globs of paths to serve even though they're denied, like /.well-known/**
+	pub allow: Vec<String>,
+	pub concurrency: Concurrency,
+	pub workers: usize,
+	pub max_connections: usize,
+	pub timeout: Duration,
+	pub entity_tags: EntityTags,
+	// Added to text content types, unless it's empty in the config
+	pub charset: Option<String>,
+	// Whether a byte order mark overrides the charset
+	pub detect_bom: bool,
+	pub cache: Cache,
+	pub mime: Vec<(String, String)>,
+	pub headers: Vec<(String, String)>,
+	pub redirects: Vec<Redirect>,
+	pub rewrites: Vec<Rewrite>,
+	pub proxies: Vec<Proxy>,
+}
+
+
+// How connections are handled at the same time
+pub enum Concurrency
+{
+	// A pool of worker threads with blocking sockets
+	Threads,
+	// A single thread with non-blocking sockets and epoll
+	#[cfg(target_os = "linux")]
+	Epoll,
+}
+
+
+// Which symbolic links in the public directory are followed
+pub enum Symlinks
+{
+	Never,
+	// Only links to files and directories that are still in the public directory
+	WithinRoot,
+	Always,
+}
+
+
+// What to do with a trailing slash in a request path, which browsers need to resolve relative links in a directory
+pub enum TrailingSlash
+{
+	// Redirect a directory to its path with a slash
+	Add,
+	// Redirect every path with a slash to the path without it
+	Strip,
+	// Serve paths with and without a slash the same way
+	Leave,
+}
+
+
+// How the entity tags of files are made
+pub enum EntityTags
+{
+	// From the size, modification time, and inode, which is cheap
+	Metadata,
+	// From a hash of the content, which stays the same when a file is rewritten with the same bytes
+	Hash,
+}
+
+
+// The Cache-Control header of files
+pub struct Cache
+{
+	pub default: String,
+	// A glob for the names of files with a hash of their content, which never change
+	pub fingerprint: Option<String>,
+	pub fingerprinted: String,
+	// Globs with their own policies, the first match winning
+	pub overrides: Vec<(String, String)>,
+}
+
+
+// Redirect an exact request path to another location
+pub struct Redirect
+{
+	pub from: String,
+	pub to: String,
+	pub status: StatusCode,
+}
+
+
+// Rewrite request paths matching a regular expression before anything else, when the conditions hold
+pub struct Rewrite
+{
+	pub pattern: Regex,
+	// The source of the pattern, to explain routes
+	pub source: String,
+	// The new path and query, with $1 to $9 for the groups of the pattern and $0 for the whole match
+	pub to: String,
+	pub method: Option<String>,
+	// A header name with an expression its value must match
+	pub header: Option<(String, Regex)>,
+	pub query: Option<Regex>,
+	pub flag: RewriteFlag,
+}
+
+
+// What happens after a rewrite rule matches
+pub enum RewriteFlag
+{
+	// Start again from the first rule with the new path
+	Last,
+	// Go on with the next rule
+	Continue,
+	// Stop rewriting
+	Break,
+}
+
+
+// Forward requests under a path prefix to another HTTP server
+pub struct Proxy
+{
+	pub path: String,
+	pub authority: String,
+	pub base_path: String,
+}
+
+
+impl Default for Config
+{
+	fn default() -> Config
+	{
+		return Config {
+			host: String::from(DEFAULT_HOSTNAME),
+			port: DEFAULT_PORT,
+			port_fallback: true,
+			root: None,
+			canonical_root: PathBuf::new(),
+			follow_symlinks: Symlinks::WithinRoot,
+			index_files: DEFAULT_INDEX_FILES.map(String::from).to_vec(),
+			trailing_slash: TrailingSlash::Add,
+			clean_urls: false,
+			redirect_html: false,
+			listing: true,
+			spa_fallback: None,
+			dev: false,
+			deny: Vec::new(),
+			allow: Vec::new(),
+			#[cfg(target_os = "linux")]
+			concurrency: Concurrency::Epoll,
+			#[cfg(not(target_os = "linux"))]
+			concurrency: Concurrency::Threads,
+			workers: DEFAULT_WORKERS,
+			max_connections: DEFAULT_MAX_CONNECTIONS,
+			timeout: DEFAULT_TIMEOUT,
+			entity_tags: EntityTags::Metadata,
+			charset: Some(String::from(DEFAULT_CHARSET)),
+			detect_bom: false,
+			cache: Cache {
+				default: String::from(DEFAULT_CACHE_CONTROL),
+				fingerprint: None,
+				fingerprinted: String::from(DEFAULT_FINGERPRINTED_CACHE_CONTROL),
+				overrides: Vec::new(),
+			},
+			mime: Vec::new(),
+			headers: Vec::new(),
+			redirects: Vec::new(),
+			rewrites: Vec::new(),
+			proxies: Vec::new(),
+		};
+	}
+}
+
+
+impl Config
+{
+	// Layer the config file, the environment, and the arguments over the defaults
+	pub fn load(args: cli::Args) -> Result<Config, Vec<String>>
+	{
+		let mut config = Config::default();
+
+		if let Some(file) = CONFIG_FILES.iter().find(|file| Path::new(file).is_file()) {
+			config.apply_file(file)?;
+		}
+		config.apply_environment().map_err(|error| vec![error])?;
+		config.apply_args(args);
+
+		// Use "public" if it exists, otherwise "."
+		let root = match config.root.take() {
+			Some(root) => {
+				if !Path::new(&root).is_dir() {
+					return Err(vec![format!("'{root}' is not a directory")]);
+				}
+				let trimmed = root.trim_end_matches('/');
+				if trimmed.is_empty() { String::from("/") } else { String::from(trimmed) }
+			},
+			None if Path::new(PREFERRED_PUBLIC_DIR).is_dir() => String::from(PREFERRED_PUBLIC_DIR),
+			None => String::from("."),
+		};
+		config.canonical_root = match Path::new(&root).canonicalize() {
+			Ok(canonical_root) => canonical_root,
+			Err(error) => return Err(vec![format!("'{root}': {error}")]),
+		};
+		config.root = Some(root);
+
+		return Ok(config);
+	}
+
+
+	// Get the resolved public directory
+	pub fn public_dir(&self) -> &str
+	{
+		return self.root.as_deref().unwrap_or(".");
+	}
+
+
+	// Read and apply a config file, reporting every problem with its line number
+	fn apply_file(&mut self, file: &str) -> Result<(), Vec<String>>
+	{
+		let text = match std::fs::read_to_string(file) {
+			Ok(text) => text,
+			Err(error) => return Err(vec![format!("{file}: {error}")]),
+		};
+
+		let tables = match toml::parse(&text) {
+			Ok(tables) => tables,
+			Err(errors) => {
+				return Err(errors.into_iter().map(|error| format!("{file}:{}: {}", error.line, error.message)).collect());
+			},
+		};
+
+		let mut errors = Vec::new();
+		for table in &tables {
+			self.apply_table(table, &mut errors);
+		}
+
+		if !errors.is_empty() {
+			errors.sort_by_key(|error: &toml::Error| error.line);
+			return Err(errors.into_iter().map(|error| format!("{file}:{}: {}", error.line, error.message)).collect());
+		}
+		return Ok(());
+	}
+
+
+	fn apply_table(&mut self, table: &toml::Table, errors: &mut Vec<toml::Error>)
+	{
+		match (table.name.as_str(), table.array) {
+			("", false) => for entry in &table.entries {
+				match entry.key.as_str() {
+					"port" => if let Some(port) = integer(entry, errors) {
+						match u16::try_from(port) {
+							Ok(port) => self.set_port(port),
+							Err(_) => error(errors, entry.line, format!("invalid port {port}, expected a number from 0 to 65535")),
+						}
+					},
+					"host" => if let Some(host) = string(entry, errors) {
+						match cli::parse_host(&host) {
+							Ok(host) => self.host = host,
+							Err(message) => error(errors, entry.line, message),
+						}
+					},
+					"root" => if let Some(root) = string(entry, errors) {
+						self.root = Some(root);
+					},
+					"concurrency" => if let Some(concurrency) = string(entry, errors) {
+						match concurrency.as_str() {
+							"threads" => self.concurrency = Concurrency::Threads,
+							#[cfg(target_os = "linux")]
+							"epoll" => self.concurrency = Concurrency::Epoll,
+							#[cfg(not(target_os = "linux"))]
+							"epoll" => error(errors, entry.line, String::from("concurrency 'epoll' is only available on Linux")),
+							_ => error(errors, entry.line, format!("invalid concurrency '{concurrency}', expected 'epoll' or 'threads'")),
+						}
+					},
+					"follow-symlinks" => if let Some(policy) = string(entry, errors) {
+						match policy.as_str() {
+							"never" => self.follow_symlinks = Symlinks::Never,
+							"within-root" => self.follow_symlinks = Symlinks::WithinRoot,
+							"always" => self.follow_symlinks = Symlinks::Always,
+							_ => error(errors, entry.line, format!("invalid follow-symlinks '{policy}', expected 'never', 'within-root', or 'always'")),
+						}
+					},
+					"index" => if let Some(names) = strings(entry, errors) {
+						match names.iter().find(|name| name.is_empty() || name.contains('/')) {
+							Some(name) => error(errors, entry.line, format!("invalid index file '{name}', expected a file name")),
+							None => self.index_files = names,
+						}
+					},
+					"trailing-slash" => if let Some(policy) = string(entry, errors) {
+						match policy.as_str() {
+							"add" => self.trailing_slash = TrailingSlash::Add,
+							"strip" => self.trailing_slash = TrailingSlash::Strip,
+							"leave" => self.trailing_slash = TrailingSlash::Leave,
+							_ => error(errors, entry.line, format!("invalid trailing-slash '{policy}', expected 'add', 'strip', or 'leave'")),
+						}
+					},
+					"clean-urls" => if let Some(clean_urls) = boolean(entry, errors) {
+						self.clean_urls = clean_urls;
+					},
+					"redirect-html" => if let Some(redirect_html) = boolean(entry, errors) {
+						self.redirect_html = redirect_html;
+					},
+					"listing" => if let Some(listing) = boolean(entry, errors) {
+						self.listing = listing;
+					},
+					"spa-fallback" => if let Some(path) = string(entry, errors) {
+						if path.starts_with('/') && !path.ends_with('/') {
+							self.spa_fallback = Some(path);
+						} else {
+							error(errors, entry.line, format!("invalid spa-fallback '{path}', expected the path of a file like '/index.html'"));
+						}
+					},
+					"deny" => if let Some(patterns) = strings(entry, errors) {
+						self.deny = patterns;
+					},
+					"allow" => if let Some(patterns) = strings(entry, errors) {
+						self.allow = patterns;
+					},
+					"etag" => if let Some(entity_tags) = string(entry, errors) {
+						match entity_tags.as_str() {
+							"metadata" => self.entity_tags = EntityTags::Metadata,
+							"hash" => self.entity_tags = EntityTags::Hash,
+							_ => error(errors, entry.line, format!("invalid etag '{entity_tags}', expected 'metadata' or 'hash'")),
+						}
+					},
+					"charset" => if let Some(charset) = string(entry, errors) {
+						if charset.is_empty() {
+							self.charset = None;
+						} else if is_header_name(&charset) {
+							self.charset = Some(charset);
+						} else {
+							error(errors, entry.line, format!("invalid charset '{charset}'"));
+						}
+					},
+					"detect-bom" => if let Some(detect_bom) = boolean(entry, errors) {
+						self.detect_bom = detect_bom;
+					},
+					"dev" => if let Some(dev) = boolean(entry, errors) {
+						self.dev = dev;
+					},
+					"nosniff" => if let Some(true) = boolean(entry, errors) {
+						self.headers.push((String::from("X-Content-Type-Options"), String::from("nosniff")));
+					},
+					"workers" => if let Some(workers) = positive_integer(entry, errors) {
+						self.workers = workers;
+					},
+					"max-connections" => if let Some(max_connections) = positive_integer(entry, errors) {
+						self.max_connections = max_connections;
+					},
+					"timeout" => if let Some(seconds) = positive_integer(entry, errors) {
+						self.timeout = Duration::from_secs(seconds as u64);
+					},
+					key => unknown_key(errors, entry.line, key, &table.name),
+				}
+			},
+			("mime", false) => for entry in &table.entries {
+				if let Some(content_type) = header_value(entry, errors) {
+					let extension = entry.key.trim_start_matches('.').to_ascii_lowercase();
+					self.mime.push((extension, content_type));
+				}
+			},
+			("headers", false) => for entry in &table.entries {
+				if let Some(value) = string(entry, errors) {
+					if !is_header_name(&entry.key) || value.contains(['\r', '\n']) {
+						error(errors, entry.line, format!("invalid header '{}'", entry.key));
+						continue;
+					}
+					self.headers.push((entry.key.clone(), value));
+				}
+			},
+			("cache", false) => for entry in &table.entries {
+				match entry.key.as_str() {
+					"default" => if let Some(value) = header_value(entry, errors) {
+						self.cache.default = value;
+					},
+					"fingerprint" => if let Some(pattern) = string(entry, errors) {
+						self.cache.fingerprint = Some(pattern);
+					},
+					"fingerprinted" => if let Some(value) = header_value(entry, errors) {
+						self.cache.fingerprinted = value;
+					},
+					key => unknown_key(errors, entry.line, key, &table.name),
+				}
+			},
+			("cache-control", false) => for entry in &table.entries {
+				if let Some(value) = header_value(entry, errors) {
+					self.cache.overrides.push((entry.key.clone(), value));
+				}
+			},
+			("redirects", true) => {
+				let mut from = None;
+				let mut to = None;
+				let mut status = StatusCode::MovedPermanently;
+				for entry in &table.entries {
+					match entry.key.as_str() {
+						"from" => from = string(entry, errors).filter(|from| is_path(errors, entry.line, from)),
+						"to" => to = string(entry, errors),
+						"status" => if let Some(code) = integer(entry, errors) {
+							match u16::try_from(code).ok().and_then(StatusCode::from_u16).filter(|code| code.is_redirect()) {
+								Some(code) => status = code,
+								None => error(errors, entry.line, format!("invalid redirect status {code}, expected 301, 302, 303, 307, or 308")),
+							}
+						},
+						key => unknown_key(errors, entry.line, key, &table.name),
+					}
+				}
+				match (from, to) {
+					(Some(from), Some(to)) => self.redirects.push(Redirect { from, to, status }),
+					_ => error(errors, table.line, String::from("[[redirects]] requires 'from' and 'to'")),
+				}
+			},
+			("rewrites", true) => {
+				let mut pattern = None;
+				let mut to = None;
+				let mut method = None;
+				let mut header = None;
+				let mut query = None;
+				let mut flag = RewriteFlag::Last;
+				for entry in &table.entries {
+					match entry.key.as_str() {
+						"pattern" => pattern = string(entry, errors).and_then(|source| regex(errors, entry.line, source)),
+						"to" => to = string(entry, errors),
+						"method" => method = string(entry, errors),
+						"header" => if let Some(condition) = string(entry, errors) {
+							match condition.split_once(':') {
+								Some((name, source)) if is_header_name(name.trim()) => {
+									header = regex(errors, entry.line, String::from(source.trim())).map(|(regex, _)| (String::from(name.trim()), regex));
+								},
+								_ => error(errors, entry.line, format!("invalid header condition '{condition}', expected 'Name: pattern'")),
+							}
+						},
+						"query" => query = string(entry, errors).and_then(|source| regex(errors, entry.line, source)).map(|(regex, _)| regex),
+						"flag" => if let Some(name) = string(entry, errors) {
+							match name.as_str() {
+								"last" => flag = RewriteFlag::Last,
+								"continue" => flag = RewriteFlag::Continue,
+								"break" => flag = RewriteFlag::Break,
+								_ => error(errors, entry.line, format!("invalid flag '{name}', expected 'last', 'continue', or 'break'")),
+							}
+						},
+						key => unknown_key(errors, entry.line, key, &table.name),
+					}
+				}
+				match (pattern, to) {
+					(Some((pattern, source)), Some(to)) => self.rewrites.push(Rewrite { pattern, source, to, method, header, query, flag }),
+					_ => error(errors, table.line, String::from("[[rewrites]] requires 'pattern' and 'to'")),
+				}
+			},
+			("proxy", true) => {
+				let mut path = None;
+				let mut target = None;
+				for entry in &table.entries {
+					match entry.key.as_str() {
+						"path" => path = string(entry, errors).filter(|path| is_path(errors, entry.line, path)),
+						"target" => if let Some(url) = string(entry, errors) {
+							match parse_http_url(&url) {
+								Some(parsed) => target = Some(parsed),
+								None => error(errors, entry.line, format!("invalid target '{url}', expected http://host:port/path")),
+							}
+						},
+						key => unknown_key(errors, entry.line, key, &table.name),
+					}
+				}
+				match (path, target) {
+					(Some(path), Some((authority, base_path))) => {
+						let path = String::from(path.trim_end_matches('/'));
+						self.proxies.push(Proxy { path, authority, base_path });
+					},
+					_ => error(errors, table.line, String::from("[[proxy]] requires 'path' and 'target'")),
+				}
+			},
+			("redirects" | "rewrites" | "proxy", false) => {
+				error(errors, table.line, format!("'{0}' must be an array of tables, written as [[{0}]]", table.name));
+			},
+			("mime" | "headers" | "cache" | "cache-control", true) => {
+				error(errors, table.line, format!("'{0}' must be a table, written as [{0}]", table.name));
+			},
+			(name, _) => error(errors, table.line, format!("unknown table '{name}'")),
+		}
+	}
+
+
+	// Use exactly this port instead of looking for a free one
+	fn set_port(&mut self, port: u16)
+	{
+		self.port = port;
+		self.port_fallback = false;
+	}
+
+
+	// Apply SERVE_* environment variables
+	fn apply_environment(&mut self) -> Result<(), String>
+	{
+		if let Ok(port) = std::env::var("SERVE_PORT") {
+			self.set_port(cli::parse_port(&port).map_err(|message| format!("SERVE_PORT: {message}"))?);
+		}
+		if let Ok(host) = std::env::var("SERVE_HOST") {
+			self.host = cli::parse_host(&host).map_err(|message| format!("SERVE_HOST: {message}"))?;
+		}
+		if let Ok(root) = std::env::var("SERVE_ROOT") {
+			self.root = Some(root);
+		}
+		return Ok(());
+	}
+
+
+	fn apply_args(&mut self, args: cli::Args)
+	{
+		if let Some(port) = args.port {
+			self.set_port(port);
+		}
+		if let Some(host) = args.host {
+			self.host = host;
+		}
+		if let Some(root) = args.root {
+			self.root = Some(root);
+		}
+	}
+}
+
+
+fn error(errors: &mut Vec<toml::Error>, line: usize, message: String)
+{
+	errors.push(toml::Error { line, message });
+}
+
+
+fn unknown_key(errors: &mut Vec<toml::Error>, line: usize, key: &str, table: &str)
+{
+	if table.is_empty() {
+		error(errors, line, format!("unknown key '{key}'"));
+	} else {
+		error(errors, line, format!("unknown key '{key}' in [{table}]"));
+	}
+}
+
+
+fn type_error(errors: &mut Vec<toml::Error>, entry: &toml::Entry, expected: &str)
+{
+	error(errors, entry.line, format!("expected {expected} for '{}', found {}", entry.key, entry.value.type_name()));
+}
+
+
+fn string(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<String>
+{
+	if let Value::String(string) = &entry.value {
+		return Some(string.clone());
+	}
+	type_error(errors, entry, "a string");
+	return None;
+}
+
+
+// Get a string that can be sent as the value of a header
+fn header_value(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<String>
+{
+	let value = string(entry, errors)?;
+	if value.contains(['\r', '\n']) {
+		error(errors, entry.line, format!("invalid header value for '{}'", entry.key));
+		return None;
+	}
+	return Some(value);
+}
+
+
+fn strings(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<Vec<String>>
+{
+	if let Value::Array(values) = &entry.value {
+		let strings: Vec<String> = values.iter().filter_map(|value| match value {
+			Value::String(string) => Some(string.clone()),
+			_ => None,
+		}).collect();
+		if strings.len() == values.len() {
+			return Some(strings);
+		}
+	}
+	type_error(errors, entry, "an array of strings");
+	return None;
+}
+
+
+fn integer(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<i64>
+{
+	if let Value::Integer(integer) = entry.value {
+		return Some(integer);
+	}
+	type_error(errors, entry, "an integer");
+	return None;
+}
+
+
+fn boolean(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<bool>
+{
+	if let Value::Boolean(boolean) = entry.value {
+		return Some(boolean);
+	}
+	type_error(errors, entry, "a boolean");
+	return None;
+}
+
+
+fn positive_integer(entry: &toml::Entry, errors: &mut Vec<toml::Error>) -> Option<usize>
+{
+	let integer = integer(entry, errors)?;
+	let positive = usize::try_from(integer).ok().filter(|integer| *integer > 0);
+	if positive.is_none() {
+		error(errors, entry.line, format!("expected a positive integer for '{}', found {integer}", entry.key));
+	}
+	return positive;
+}
+
+
+// Require a path to start with a slash
+fn is_path(errors: &mut Vec<toml::Error>, line: usize, path: &str) -> bool
+{
+	if !path.starts_with('/') {
+		error(errors, line, format!("invalid path '{path}', expected it to start with '/'"));
+		return false;
+	}
+	return true;
+}
+
+
+// Compile a regular expression, keeping its source
+fn regex(errors: &mut Vec<toml::Error>, line: usize, source: String) -> Option<(Regex, String)>
+{
+	return match Regex::new(&source) {
+		Ok(regex) => Some((regex, source)),
+		Err(message) => {
+			error(errors, line, format!("invalid pattern '{source}': {message}"));
+			None
+		},
+	};
+}
+
+
+pub fn is_header_name(name: &str) -> bool
+{
+	return !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte));
+}
+
+
+// Split "http://host:port/path" into its authority and path
+fn parse_http_url(url: &str) -> Option<(String, String)>
+{
+	let rest = url.strip_prefix("http://")?;
+	let (authority, path) = match rest.find('/') {
+		Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+		None => (rest, ""),
+	};
+	if authority.is_empty() {
+		return None;
+	}
+	let authority = if authority.contains(':') && !authority.ends_with(']') {
+		String::from(authority)
+	} else {
+		format!("{authority}:80")
+	};
+	return Some((authority, String::from(path)));
+}
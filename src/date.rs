@@ -0,0 +1,124 @@
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+
+// Format the seconds since 1970 as an HTTP date like "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn format(seconds: u64) -> String
+{
+	let days = seconds / 86400;
+	let (year, month, day) = civil_from_days(days);
+	let weekday = WEEKDAYS[((days + 4) % 7) as usize];
+	let (hour, minute, second) = (seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+	return format!("{weekday}, {day:02} {} {year} {hour:02}:{minute:02}:{second:02} GMT", MONTHS[month as usize - 1]);
+}
+
+
+// Parse an HTTP date in the preferred format or one of the obsolete ones, getting the seconds since 1970
+pub fn parse(text: &str) -> Option<u64>
+{
+	let words: Vec<&str> = text.split(' ').filter(|word| !word.is_empty()).collect();
+	let (day, month, year, time) = match words.as_slice() {
+		// Sun, 06 Nov 1994 08:49:37 GMT
+		[_, day, month, year, time, "GMT"] => (*day, *month, year.parse().ok()?, *time),
+		// Sunday, 06-Nov-94 08:49:37 GMT
+		[_, date, time, "GMT"] => {
+			let mut parts = date.split('-');
+			let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+			let year: u64 = if year.len() == 2 { year.parse().ok()? } else { return None };
+			(day, month, if year < 70 { 2000 + year } else { 1900 + year }, *time)
+		},
+		// Sun Nov  6 08:49:37 1994
+		[_, month, day, time, year] => (*day, *month, year.parse().ok()?, *time),
+		_ => return None,
+	};
+
+	let day: u64 = day.parse().ok().filter(|day| (1..=31).contains(day))?;
+	let month = MONTHS.iter().position(|name| *name == month)? as u64 + 1;
+	// Years outside of four digits aren't HTTP dates, and would overflow the seconds
+	if !(1970..=9999).contains(&year) {
+		return None;
+	}
+	let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+	let (hour, minute, second) = (parts.next()??, parts.next()??, parts.next()??);
+	if hour > 23 || minute > 59 || second > 60 || parts.next().is_some() {
+		return None;
+	}
+
+	return Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second);
+}
+
+
+// Convert days since 1970-01-01 to a year, month, and day of the Gregorian calendar
+fn civil_from_days(days: u64) -> (u64, u64, u64)
+{
+	// Count from 0000-03-01 in eras of 400 years, so leap days come at the end of each year
+	let days = days + 719468;
+	let era = days / 146097;
+	let day_of_era = days % 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = year_of_era + era * 400 + (month <= 2) as u64;
+	return (year, month, day);
+}
+
+
+// Convert a year, month, and day of the Gregorian calendar to days since 1970-01-01
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64
+{
+	let year = year - (month <= 2) as u64;
+	let era = year / 400;
+	let year_of_era = year % 400;
+	let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+	let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	return era * 146097 + day_of_era - 719468;
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	#[test]
+	fn round_trips()
+	{
+		let cases = [
+			(0, "Thu, 01 Jan 1970 00:00:00 GMT"),
+			(784111777, "Sun, 06 Nov 1994 08:49:37 GMT"),
+			(951782400, "Tue, 29 Feb 2000 00:00:00 GMT"),
+			(4133980799, "Fri, 31 Dec 2100 23:59:59 GMT"),
+			(253402300799, "Fri, 31 Dec 9999 23:59:59 GMT"),
+		];
+		for (seconds, text) in cases {
+			assert_eq!(super::format(seconds), text);
+			assert_eq!(super::parse(text), Some(seconds), "{text}");
+		}
+	}
+
+
+	#[test]
+	fn parse()
+	{
+		let cases = [
+			("Sunday, 06-Nov-94 08:49:37 GMT", Some(784111777)),
+			("Sun Nov  6 08:49:37 1994", Some(784111777)),
+			("Thursday, 01-Jan-15 00:00:00 GMT", Some(1420070400)),
+			("Sun, 06 Nov 1994 08:49:37 UTC", None),
+			("Sun, 32 Nov 1994 08:49:37 GMT", None),
+			("Sun, 06 Foo 1994 08:49:37 GMT", None),
+			("Sun, 06 Nov 1994 24:00:00 GMT", None),
+			("Sun, 06 Nov 1994 08:49 GMT", None),
+			("Wed, 31 Dec 1969 23:59:59 GMT", None),
+			("Sat, 01 Jan 10000 00:00:00 GMT", None),
+			("Sun, 06 Nov 18446744073709551615 08:49:37 GMT", None),
+			("Sun Nov  6 08:49:37 99999999999999", None),
+			("", None),
+		];
+		for (text, expected) in cases {
+			assert_eq!(super::parse(text), expected, "{text}");
+		}
+	}
+}
@@ -0,0 +1,73 @@
+// Pages for error responses, from a file like 404.html in the public directory as static hosts do, or built in
+
+
+use std::path::Path;
+
+use crate::config::Config;
+use crate::listing;
+use crate::response::Body;
+use crate::response::Response;
+use crate::sandbox;
+use crate::sandbox::Resolved;
+
+
+// Give an error response without content a page, with details about the request in dev mode
+pub fn render(config: &Config, response: Response, partial_path: Option<&str>) -> Response
+{
+	let status_code = response.status_code as u16;
+	if status_code < 400 || response.body.len() > 0 {
+		return response;
+	}
+
+	let (mut page, content_type) = match custom(config, status_code) {
+		Some(page) => {
+			let content_type = match &config.charset {
+				Some(charset) => format!("text/html; charset={charset}"),
+				None => String::from("text/html"),
+			};
+			(page, content_type)
+		},
+		None => {
+			let title = format!("{status_code} {}", response.status_code.reason());
+			let page = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n</body>\n</html>\n");
+			(page.into_bytes(), String::from("text/html; charset=utf-8"))
+		},
+	};
+	if config.dev {
+		if let Some(partial_path) = partial_path {
+			insert_details(config, &mut page, partial_path);
+		}
+	}
+
+	let response = Response { status_code: response.status_code, headers: response.headers, body: Body::Bytes(page) };
+	return response.with_header("Content-Type", &content_type);
+}
+
+
+// Read the page for a status code from the public directory
+fn custom(config: &Config, status_code: u16) -> Option<Vec<u8>>
+{
+	return match sandbox::resolve(config, &format!("/{status_code}.html")) {
+		Resolved::Found(path) if path.is_file() => std::fs::read(path).ok(),
+		_ => None,
+	};
+}
+
+
+// Show which file was looked for, before the end of the body or at the end of the page
+fn insert_details(config: &Config, page: &mut Vec<u8>, partial_path: &str)
+{
+	let path = Path::new(config.public_dir()).join(partial_path.trim_start_matches('/'));
+	let mut details = format!(
+		"<pre>Request path: {}\nFile path: {}\n",
+		listing::escape(partial_path),
+		listing::escape(&path.display().to_string()),
+	);
+	if let Ok(canonical) = path.canonicalize() {
+		details.push_str(&format!("Resolved path: {}\n", listing::escape(&canonical.display().to_string())));
+	}
+	details.push_str("</pre>\n");
+
+	let end = page.windows(7).rposition(|window| window.eq_ignore_ascii_case(b"</body>")).unwrap_or(page.len());
+	page.splice(end..end, details.into_bytes());
+}
@@ -0,0 +1,125 @@
+// Match paths against patterns where * matches anything but a slash, ** matches anything, ? matches one character
+// other than a slash, and [...] matches one character of a set like [a-z0-9] or one not in a set like [!.]
+
+
+// Check whether a request path matches a pattern, or its file name does when the pattern has no slash
+pub fn matches_path(pattern: &str, path: &str) -> bool
+{
+	if pattern.contains('/') {
+		return matches(pattern, path);
+	}
+	return matches(pattern, path.rsplit('/').next().unwrap_or(path));
+}
+
+
+pub fn matches(pattern: &str, text: &str) -> bool
+{
+	let pattern: Vec<char> = pattern.chars().collect();
+	let text: Vec<char> = text.chars().collect();
+	return match_from(&pattern, &text);
+}
+
+
+fn match_from(pattern: &[char], text: &[char]) -> bool
+{
+	match pattern {
+		[] => return text.is_empty(),
+		// Anything, where **/ can also match no directories
+		['*', '*', rest @ ..] => {
+			if let ['/', after_slash @ ..] = rest {
+				if match_from(after_slash, text) {
+					return true;
+				}
+			}
+			return (0..=text.len()).any(|start| match_from(rest, &text[start..]));
+		},
+		// Anything in one path segment
+		['*', rest @ ..] => {
+			for start in 0..=text.len() {
+				if match_from(rest, &text[start..]) {
+					return true;
+				}
+				if text.get(start) == Some(&'/') {
+					return false;
+				}
+			}
+			return false;
+		},
+		['?', rest @ ..] => return matches!(text, [character, ..] if *character != '/') && match_from(rest, &text[1..]),
+		['[', rest @ ..] => {
+			if let Some((negated, set, after_set)) = split_set(rest) {
+				return match text {
+					[character, ..] if *character != '/' && in_set(set, *character) != negated => match_from(after_set, &text[1..]),
+					_ => false,
+				};
+			}
+			return text.first() == Some(&'[') && match_from(rest, &text[1..]);
+		},
+		[literal, rest @ ..] => return text.first() == Some(literal) && match_from(rest, &text[1..]),
+	}
+}
+
+
+// Split the inside of [...] from the rest of the pattern, or get nothing if it isn't closed
+fn split_set(pattern: &[char]) -> Option<(bool, &[char], &[char])>
+{
+	let (negated, pattern) = match pattern {
+		['!' | '^', rest @ ..] => (true, rest),
+		_ => (false, pattern),
+	};
+
+	// A ] right after the opening bracket is part of the set
+	let end = pattern.iter().skip(1).position(|character| *character == ']')? + 1;
+	return Some((negated, &pattern[..end], &pattern[end + 1..]));
+}
+
+
+fn in_set(set: &[char], character: char) -> bool
+{
+	let mut index = 0;
+	while index < set.len() {
+		if index + 2 < set.len() && set[index + 1] == '-' {
+			if (set[index]..=set[index + 2]).contains(&character) {
+				return true;
+			}
+			index += 3;
+		} else {
+			if set[index] == character {
+				return true;
+			}
+			index += 1;
+		}
+	}
+	return false;
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	#[test]
+	fn matches_path()
+	{
+		let cases = [
+			("*.css", "/assets/app.css", true),
+			("*.css", "/assets/app.css/x", false),
+			("*~", "/notes/a.md~", true),
+			("/assets/*", "/assets/app.js", true),
+			("/assets/*", "/assets/vendor/lib.js", false),
+			("/assets/**", "/assets/vendor/lib.js", true),
+			("/**/*.map", "/app.js.map", true),
+			("/**/*.map", "/a/b/app.js.map", true),
+			("/a?c", "/abc", true),
+			("/a?c", "/a/c", false),
+			("/v[0-9]/*", "/v1/x", true),
+			("/v[!0-9]/*", "/v1/x", false),
+			("/[]]", "/]", true),
+			("/[a", "/[a", true),
+			("/private/**", "/private", false),
+			("*.css", "/app.CSS", false),
+		];
+		for (pattern, path, expected) in cases {
+			assert_eq!(super::matches_path(pattern, path), expected, "{pattern} on {path}");
+		}
+	}
+}
@@ -0,0 +1,510 @@
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::config::Proxy;
+use crate::config::TrailingSlash;
+use crate::conditional;
+use crate::conditional::Validators;
+use crate::error_page;
+use crate::glob;
+use crate::listing;
+use crate::mime;
+use crate::proxy;
+use crate::range;
+use crate::range::Ranges;
+use crate::request;
+use crate::rewrite;
+use crate::sandbox;
+use crate::sandbox::Resolved;
+use crate::sniff;
+use crate::uri;
+use crate::request::Request;
+use crate::request::Version;
+use crate::response::Response;
+use crate::response::StatusCode;
+use crate::rules;
+use crate::rules::Action;
+use crate::rules::Rules;
+
+
+const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+
+// What to do with a request
+pub enum Route<'a>
+{
+	Respond(Response),
+	Proxy(&'a Proxy),
+}
+
+
+// Decide how to answer a request, after rewriting its path
+pub fn route<'a>(config: &'a Config, request: &mut Request) -> Route<'a>
+{
+	let rewritten = match rewrite::apply(config, request, None) {
+		Ok(rewritten) => rewritten,
+		Err(message) => {
+			eprintln!("serve: {message}");
+			return Route::Respond(error_page::render(config, Response::simple(StatusCode::InternalServerError), None).with_headers(&config.headers));
+		},
+	};
+	return dispatch(config, request, rewritten);
+}
+
+
+// Describe how a GET request for a path and query would be answered, for --explain-route
+pub fn explain(config: &Config, target: &str) -> Result<Vec<String>, String>
+{
+	let mut request = request::parse(format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+		.map_err(|_| format!("invalid path '{target}'"))?;
+	let mut lines = Vec::new();
+	let rewritten = rewrite::apply(config, &mut request, Some(&mut lines))?;
+	if !rewritten {
+		lines.push(String::from("no rewrite rule matched"));
+	} else {
+		let query = request.query.as_deref().map(|query| format!("?{query}")).unwrap_or_default();
+		lines.push(format!("rewritten to {}{query}", request.path));
+	}
+
+	match dispatch(config, &request, rewritten) {
+		Route::Proxy(proxy) => lines.push(format!("forwarded to http://{}{}{}", proxy.authority, proxy.base_path, &request.path[proxy.path.len()..])),
+		Route::Respond(response) => {
+			let status_code = response.status_code;
+			let mut line = format!("response {} {}", status_code as u16, status_code.reason());
+			for (name, value) in response.headers.iter().filter(|(name, _)| name == "Location" || name == "Content-Type") {
+				line.push_str(&format!(", {name}: {value}"));
+			}
+			lines.push(line);
+		},
+	}
+	return Ok(lines);
+}
+
+
+// Decide how to answer a request after the rewrite rules, which may have changed its path
+fn dispatch<'a>(config: &'a Config, request: &Request, rewritten: bool) -> Route<'a>
+{
+	let decoded_path = uri::decode(&request.path);
+
+	// Forward the request to a configured server, whatever the method, unless it's redirected or its path can't be decoded,
+	// which could hide dot segments from the prefix match
+	let redirected = decoded_path.as_deref().is_some_and(|path| config.redirects.iter().any(|redirect| redirect.from == path));
+	if let Some(proxy) = proxy::find(&config.proxies, &request.path).filter(|_| decoded_path.is_some() && !redirected) {
+		return Route::Proxy(proxy);
+	}
+
+	let rules = rules::load(config);
+	let response = respond(config, &rules, request, decoded_path.as_deref(), rewritten);
+	let mut response = error_page::render(config, response, decoded_path.as_deref()).with_headers(&config.headers);
+	if let Some(path) = &decoded_path {
+		rules.apply_headers(path, &mut response);
+	}
+	return Route::Respond(response);
+}
+
+
+// Create an error response for a request that couldn't be read
+pub fn error(config: &Config, status_code: StatusCode) -> Response
+{
+	let response = error_page::render(config, Response::simple(status_code), None);
+	return response.with_headers(&config.headers).with_header("Connection", "close");
+}
+
+
+// Tell the client whether the connection stays open after the response
+pub fn set_connection(response: &mut Response, request: &Request, keep_alive: bool)
+{
+	if !keep_alive {
+		response.headers.push((String::from("Connection"), String::from("close")));
+	} else if request.version == Version::Http10 {
+		response.headers.push((String::from("Connection"), String::from("keep-alive")));
+	}
+}
+
+
+// Create the response to a request for a file, without redirecting a rewritten path to its canonical form, which would
+// show the client where it was rewritten to
+fn respond(config: &Config, rules: &Rules, request: &Request, decoded_path: Option<&str>, rewritten: bool) -> Response
+{
+	// See a path that decodes to file names or send an error response
+	let Some(partial_path) = decoded_path else {
+		return Response::simple(StatusCode::BadRequest);
+	};
+
+	// Send a configured redirect
+	if let Some(redirect) = config.redirects.iter().find(|redirect| redirect.from == partial_path) {
+		let mut location = uri::encode_location(&redirect.to);
+		if let Some(query) = request.query.as_deref().filter(|_| !redirect.to.contains('?')) {
+			location.push('?');
+			location.push_str(&uri::encode_location(query));
+		}
+		return Response::redirect(redirect.status, &location);
+	}
+
+	// Follow the first rule of _redirects, or remember the path it serves instead
+	let rewrite = match rules.route(request, partial_path, || is_served(config, partial_path)) {
+		Some(Action::Redirect(status_code, location)) => return Response::redirect(status_code, &location),
+		Some(Action::Rewrite(status_code, path)) => Some((status_code, path)),
+		None => None,
+	};
+
+	// See a GET or HEAD request, answer OPTIONS, or send an error response
+	match request.method.as_str() {
+		"GET" | "HEAD" => (),
+		"OPTIONS" => return Response::simple(StatusCode::NoContent).with_header("Allow", ALLOWED_METHODS),
+		"POST" | "PUT" | "DELETE" | "PATCH" | "CONNECT" | "TRACE" => {
+			return Response::simple(StatusCode::MethodNotAllowed).with_header("Allow", ALLOWED_METHODS);
+		},
+		_ => return Response::simple(StatusCode::NotImplemented),
+	}
+
+	// Serve the path of a rewrite rule, possibly as the content of a 404 response
+	if let Some((status_code, path)) = rewrite {
+		let mut response = serve(config, request, &path, true);
+		if response.status_code == StatusCode::Ok {
+			response.status_code = status_code;
+		}
+		return response;
+	}
+
+	// Redirect a path with a trailing slash to the path without it
+	if matches!(config.trailing_slash, TrailingSlash::Strip) && !rewritten && partial_path != "/" && partial_path.ends_with('/') {
+		return redirect(request, partial_path.trim_end_matches('/'));
+	}
+
+	// Redirect /about.html to /about, and /index.html to /, with clean URLs
+	if let Some(clean_path) = html_redirect(config, partial_path).filter(|_| !rewritten) {
+		return redirect(request, &clean_path);
+	}
+
+	// Let a single-page application handle routes that aren't files, when the client wants HTML
+	if let Some(fallback) = config.spa_fallback.as_deref().filter(|_| is_client_route(config, partial_path)) {
+		let response = if request.headers.has_token("Accept", "text/html") {
+			serve(config, request, fallback, true)
+		} else {
+			Response::simple(StatusCode::NotFound)
+		};
+		return response.with_header("Vary", "Accept");
+	}
+	return serve(config, request, partial_path, rewritten);
+}
+
+
+// Create the response to a GET or HEAD request for a file or directory, only redirecting to add a slash when the path
+// wasn't rewritten
+fn serve(config: &Config, request: &Request, partial_path: &str, rewritten: bool) -> Response
+{
+	// Act as if hidden and sensitive files don't exist
+	if sandbox::is_denied(config, partial_path) {
+		return Response::simple(StatusCode::NotFound);
+	}
+
+	// Find the file or directory in the public directory, or an HTML file for a clean URL, then possibly an index file
+	let mut served_path = String::from(partial_path);
+	let mut path = match find(config, partial_path) {
+		Ok(path) => path,
+		Err(response) => match clean_path(config, partial_path).filter(|_| response.status_code == StatusCode::NotFound) {
+			Some((html_path, path)) => {
+				served_path = html_path;
+				path
+			},
+			None => return response,
+		},
+	};
+	if path.is_dir() {
+		// To fix relative paths, redirect by adding a trailing slash
+		if matches!(config.trailing_slash, TrailingSlash::Add) && !rewritten && !partial_path.ends_with('/') {
+			return redirect(request, &format!("{partial_path}/"));
+		}
+
+		// Serve the first index file that exists, otherwise list the directory
+		let directory_path = format!("{}/", partial_path.trim_end_matches('/'));
+		let index = config.index_files.iter().find_map(|name| {
+			let index_path = format!("{directory_path}{name}");
+			return match sandbox::resolve(config, &index_path) {
+				Resolved::Found(path) if path.is_file() => Some((index_path, path)),
+				_ => None,
+			};
+		});
+		(served_path, path) = match index {
+			Some(index) => index,
+			None if config.listing && listing::wants_json(request) => return listing::json(config, request, &directory_path, &path),
+			None if config.listing => return listing::html(config, request, &directory_path, &path),
+			None => return Response::simple(StatusCode::NotFound),
+		};
+	}
+
+	// Open the file and get its length or send an error response
+	let file = match File::open(&path) {
+		Ok(file) => file,
+		Err(_) => return Response::simple(StatusCode::NotFound),
+	};
+	let metadata = match file.metadata() {
+		Ok(metadata) if metadata.is_file() => metadata,
+		_ => return Response::simple(StatusCode::NotFound),
+	};
+	let length = metadata.len();
+	let content_type = content_type(config, Path::new(&served_path), &file);
+	let cache_control = cache_control(config, &served_path);
+
+	// Tell the client its copy is current, or that the file isn't the version it expects
+	let validators = Validators::new(config, &file, &metadata);
+	if let Some(status_code) = conditional::evaluate(&request.headers, &validators) {
+		return Response::simple(status_code).with_header("Cache-Control", cache_control).with_headers(&validators.headers());
+	}
+
+	// Send the requested ranges of a GET request, unless the file changed since the client saw it
+	let ranges = match request.headers.get("Range") {
+		Some(value) if request.method == "GET" && conditional::if_range(&request.headers, &validators) => range::parse(value, length),
+		_ => Ranges::All,
+	};
+
+	// Finally send the file content, which is read while it's sent
+	let response = match ranges {
+		Ranges::All => Response::file(&content_type, file, length),
+		Ranges::Satisfiable(ranges) => range::respond(&content_type, file, length, &ranges),
+		Ranges::Unsatisfiable => {
+			return Response::simple(StatusCode::RangeNotSatisfiable).with_header("Content-Range", &format!("bytes */{length}"));
+		},
+	};
+	return response
+		.with_header("Accept-Ranges", "bytes")
+		.with_header("Cache-Control", cache_control)
+		.with_headers(&validators.headers());
+}
+
+
+// Check whether a path leads to a file or directory that isn't denied, directly or as a clean URL
+fn is_served(config: &Config, partial_path: &str) -> bool
+{
+	if sandbox::is_denied(config, partial_path) {
+		return false;
+	}
+	return matches!(sandbox::resolve(config, partial_path), Resolved::Found(_)) || clean_path(config, partial_path).is_some();
+}
+
+
+// Check whether a path that doesn't exist has no extension, so it's a route of the application rather than a missing asset
+fn is_client_route(config: &Config, partial_path: &str) -> bool
+{
+	let name = partial_path.rsplit('/').next().unwrap_or("");
+	return !name.contains('.')
+		&& matches!(sandbox::resolve(config, partial_path), Resolved::Missing)
+		&& clean_path(config, partial_path).is_none();
+}
+
+
+// Find the HTML file of a clean URL, like about.html for /about or /about/
+fn clean_path(config: &Config, partial_path: &str) -> Option<(String, PathBuf)>
+{
+	let base_path = partial_path.trim_end_matches('/');
+	if !config.clean_urls || base_path.is_empty() || base_path.ends_with(".html") {
+		return None;
+	}
+	let html_path = format!("{base_path}.html");
+	if sandbox::is_denied(config, &html_path) {
+		return None;
+	}
+	return match sandbox::resolve(config, &html_path) {
+		Resolved::Found(path) if path.is_file() => Some((html_path, path)),
+		_ => None,
+	};
+}
+
+
+// Get the clean URL of a request for an HTML file, unless the clean URL would lead somewhere else
+fn html_redirect(config: &Config, partial_path: &str) -> Option<String>
+{
+	if !config.clean_urls || !config.redirect_html || sandbox::is_denied(config, partial_path) {
+		return None;
+	}
+	let clean_path = partial_path.strip_suffix(".html")?;
+	if !matches!(sandbox::resolve(config, partial_path), Resolved::Found(path) if path.is_file()) {
+		return None;
+	}
+
+	// An index file is served for its directory, unless another index file comes first
+	if let Some(directory) = clean_path.strip_suffix("/index") {
+		let directory_path = partial_path.strip_suffix("index.html")?;
+		let index_name = config.index_files.iter().find(|name| {
+			return matches!(sandbox::resolve(config, &format!("{directory_path}{name}")), Resolved::Found(path) if path.is_file());
+		});
+		if index_name.map(String::as_str) != Some("index.html") {
+			return None;
+		}
+		return match config.trailing_slash {
+			TrailingSlash::Strip if !directory.is_empty() => Some(String::from(directory)),
+			_ => Some(format!("{directory}/")),
+		};
+	}
+	if !matches!(sandbox::resolve(config, clean_path), Resolved::Missing) {
+		return None;
+	}
+	return Some(String::from(clean_path));
+}
+
+
+// Redirect permanently to a decoded path on this server, keeping the query of the request
+fn redirect(request: &Request, partial_path: &str) -> Response
+{
+	let mut location = uri::encode_path(partial_path);
+	if let Some(query) = &request.query {
+		location.push('?');
+		location.push_str(&uri::encode_location(query));
+	}
+	return Response::redirect(StatusCode::PermanentRedirect, &location);
+}
+
+
+// Find a file or directory under the symbolic link policy, or create an error response
+fn find(config: &Config, partial_path: &str) -> Result<PathBuf, Response>
+{
+	return match sandbox::resolve(config, partial_path) {
+		Resolved::Found(path) => Ok(path),
+		Resolved::Missing => Err(Response::simple(StatusCode::NotFound)),
+		Resolved::Denied(reason) => {
+			eprintln!("serve: denied {partial_path}: {reason}");
+			Err(Response::simple(StatusCode::Forbidden))
+		},
+	};
+}
+
+
+// Get the content type from the config, the built-in types, or the content of a file without an extension, with a charset for text
+fn content_type(config: &Config, path: &Path, file: &File) -> String
+{
+	let extension = path.extension().and_then(|os_str| os_str.to_str()).map(str::to_ascii_lowercase).unwrap_or_default();
+	let known_type = mime::find(config, &extension);
+
+	// Only read the start of the file to sniff it or to look for a byte order mark
+	let header = if (known_type.is_none() && extension.is_empty()) || config.detect_bom {
+		sniff::read_header(file).unwrap_or_default()
+	} else {
+		Vec::new()
+	};
+	let content_type = match known_type {
+		Some(content_type) => content_type,
+		None if extension.is_empty() => sniff::sniff(&header),
+		None => mime::DEFAULT_TYPE,
+	};
+
+	// Keep a configured charset, otherwise prefer the byte order mark
+	if content_type.contains(';') || !mime::is_text(content_type) {
+		return String::from(content_type);
+	}
+	let bom_charset = if config.detect_bom { sniff::bom_charset(&header) } else { None };
+	return match bom_charset.or(config.charset.as_deref()) {
+		Some(charset) => format!("{content_type}; charset={charset}"),
+		None => String::from(content_type),
+	};
+}
+
+
+// Get the caching policy of a file from the first matching glob, whether its name is fingerprinted, or the default
+fn cache_control<'a>(config: &'a Config, path: &str) -> &'a str
+{
+	let cache = &config.cache;
+	if let Some((_, value)) = cache.overrides.iter().find(|(pattern, _)| glob::matches_path(pattern, path)) {
+		return value;
+	}
+	if cache.fingerprint.as_ref().is_some_and(|pattern| glob::matches_path(pattern, path)) {
+		return &cache.fingerprinted;
+	}
+	return &cache.default;
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::Route;
+	use crate::config::Config;
+	use crate::config::Rewrite;
+	use crate::config::RewriteFlag;
+	use crate::config::TrailingSlash;
+	use crate::regex::Regex;
+	use crate::request;
+	use crate::response::Response;
+	use crate::testing::TempDir;
+
+
+	fn rewrite(pattern: &str, to: &str) -> Rewrite
+	{
+		return Rewrite {
+			pattern: Regex::new(pattern).unwrap(),
+			source: String::from(pattern),
+			to: String::from(to),
+			method: None,
+			header: None,
+			query: None,
+			flag: RewriteFlag::Last,
+		};
+	}
+
+
+	fn respond(config: &Config, target: &str) -> Response
+	{
+		let mut request = request::parse(format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes()).ok().unwrap();
+		let Route::Respond(response) = super::route(config, &mut request) else {
+			panic!("{target} was proxied");
+		};
+		return response;
+	}
+
+
+	// Route a GET request, getting the status code and the location of a redirect
+	fn get(config: &Config, target: &str) -> (u16, Option<String>)
+	{
+		let response = respond(config, target);
+		let location = response.headers.iter().find(|(name, _)| name == "Location").map(|(_, value)| value.clone());
+		return (response.status_code as u16, location);
+	}
+
+
+	#[test]
+	fn rewritten_paths_are_not_redirected()
+	{
+		let directory = TempDir::new("rewritten-paths");
+		directory.file("blog/post.html", "post");
+		directory.file("docs/index.html", "docs");
+		let mut config = directory.config();
+		config.clean_urls = true;
+		config.redirect_html = true;
+		config.rewrites = vec![rewrite("^/b/(\\d+)$", "/blog/post.html?id=$1"), rewrite("^/d$", "/docs"), rewrite("^/s$", "/docs/")];
+
+		let cases = [
+			("/b/12", (200, None)),
+			("/d", (200, None)),
+			("/blog/post.html?id=12", (308, Some("/blog/post?id=12"))),
+			("/docs", (308, Some("/docs/"))),
+		];
+		for (target, (status_code, location)) in cases {
+			assert_eq!(get(&config, target), (status_code, location.map(String::from)), "{target}");
+		}
+
+		config.trailing_slash = TrailingSlash::Strip;
+		assert_eq!(get(&config, "/s"), (200, None));
+		assert_eq!(get(&config, "/docs/"), (308, Some(String::from("/docs"))));
+	}
+
+
+	#[test]
+	fn files_shadow_redirect_rules()
+	{
+		let directory = TempDir::new("files-shadow-redirect-rules");
+		directory.file("about.html", "about page");
+		directory.file("forced.html", "forced page");
+		directory.file("blog/post.html", "post");
+		directory.file("_redirects", "/about /blog/post.html 200\n/forced /blog/post.html 200!\n/missing /blog/post.html 200\n");
+		let mut config = directory.config();
+		config.clean_urls = true;
+
+		let cases = [("/about", 200, 10), ("/forced", 200, 4), ("/missing", 200, 4)];
+		for (target, status_code, length) in cases {
+			let response = respond(&config, target);
+			assert_eq!((response.status_code as u16, response.body.len()), (status_code, length), "{target}");
+		}
+	}
+}
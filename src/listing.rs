@@ -0,0 +1,355 @@
+// Pages that list the files in a directory without an index file, as HTML or as JSON for tools
+
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::config::Config;
+use crate::config::TrailingSlash;
+use crate::date;
+use crate::mime;
+use crate::request::Request;
+use crate::response::Response;
+use crate::response::StatusCode;
+use crate::sandbox;
+use crate::sandbox::Resolved;
+use crate::uri;
+
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em}table{border-collapse:collapse}\
+th,td{padding:.2em 1em;text-align:left}td.size{text-align:right;font-variant-numeric:tabular-nums}\
+a{text-decoration:none}a:hover{text-decoration:underline}";
+
+// How deep a recursive JSON listing goes into directories, and how many entries it has at most
+const MAX_DEPTH: usize = 16;
+const MAX_JSON_ENTRIES: usize = 10000;
+
+
+// A file or directory in a listing
+pub struct Entry
+{
+	pub name: String,
+	// Where the entry leads after following a link
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub size: u64,
+	// Seconds since 1970
+	pub modified: Option<u64>,
+	// Where a symbolic link points
+	pub target: Option<String>,
+}
+
+
+// The column a listing is sorted by
+#[derive(Clone, Copy, PartialEq)]
+pub enum Column
+{
+	Name,
+	Size,
+	Modified,
+}
+
+
+// How a listing is sorted, from the query parameters sort=name|size|modified and order=asc|desc
+#[derive(Clone, Copy)]
+pub struct Sort
+{
+	pub column: Column,
+	pub descending: bool,
+}
+
+
+impl Sort
+{
+	pub fn from_query(query: Option<&str>) -> Sort
+	{
+		let mut sort = Sort { column: Column::Name, descending: false };
+		for (name, value) in parameters(query) {
+			match (name, value) {
+				("sort", "name") => sort.column = Column::Name,
+				("sort", "size") => sort.column = Column::Size,
+				("sort", "modified") => sort.column = Column::Modified,
+				("order", "asc") => sort.descending = false,
+				("order", "desc") => sort.descending = true,
+				_ => (),
+			}
+		}
+		return sort;
+	}
+}
+
+
+impl Column
+{
+	fn name(self) -> &'static str
+	{
+		return match self {
+			Column::Name => "name",
+			Column::Size => "size",
+			Column::Modified => "modified",
+		};
+	}
+}
+
+
+// Read the entries of a directory that can be served, leaving out denied files and links that can't be followed
+pub fn read(config: &Config, partial_path: &str, directory: &Path, sort: Sort) -> std::io::Result<Vec<Entry>>
+{
+	let mut entries = Vec::new();
+	for entry in std::fs::read_dir(directory)? {
+		let entry = entry?;
+		let Ok(name) = entry.file_name().into_string() else {
+			continue;
+		};
+		let entry_path = format!("{partial_path}{name}");
+		if sandbox::is_denied(config, &entry_path) {
+			continue;
+		}
+		let Resolved::Found(path) = sandbox::resolve(config, &entry_path) else {
+			continue;
+		};
+		let Ok(metadata) = path.metadata() else {
+			continue;
+		};
+
+		let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|duration| duration.as_secs());
+		let target = if entry.file_type().is_ok_and(|file_type| file_type.is_symlink()) {
+			std::fs::read_link(entry.path()).ok().map(|target| target.to_string_lossy().into_owned())
+		} else {
+			None
+		};
+		entries.push(Entry { name, path, is_dir: metadata.is_dir(), size: metadata.len(), modified, target });
+	}
+
+	// Keep directories first, whatever the order
+	entries.sort_by(|a, b| {
+		let ordering = match sort.column {
+			Column::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| a.name.cmp(&b.name)),
+			Column::Size => a.size.cmp(&b.size),
+			Column::Modified => a.modified.cmp(&b.modified),
+		};
+		let ordering = if sort.descending { ordering.reverse() } else { ordering };
+		return b.is_dir.cmp(&a.is_dir).then(ordering);
+	});
+	return Ok(entries);
+}
+
+
+// Create an HTML page listing a directory, whose path ends with a slash, with links that work whether the request path
+// ends with one or not
+pub fn html(config: &Config, request: &Request, partial_path: &str, directory: &Path) -> Response
+{
+	let base = format!("{}/", request.path.trim_end_matches('/'));
+	let slash = if matches!(config.trailing_slash, TrailingSlash::Strip) { "" } else { "/" };
+	let sort = Sort::from_query(request.query.as_deref());
+	let entries = match read(config, partial_path, directory, sort) {
+		Ok(entries) => entries,
+		Err(_) => return Response::simple(StatusCode::NotFound),
+	};
+
+	let title = escape(&format!("Index of {partial_path}"));
+	let mut page = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<table>\n<tr>");
+	for (column, label) in [(Column::Name, "Name"), (Column::Size, "Size"), (Column::Modified, "Modified")] {
+		// Clicking the current column reverses the order
+		let descending = sort.column == column && !sort.descending;
+		let order = if descending { "desc" } else { "asc" };
+		let arrow = match (sort.column == column, sort.descending) {
+			(true, false) => " &#9650;",
+			(true, true) => " &#9660;",
+			(false, _) => "",
+		};
+		page.push_str(&format!("<th><a href=\"?sort={}&amp;order={order}\">{label}</a>{arrow}</th>", column.name()));
+	}
+	page.push_str("</tr>\n");
+
+	if partial_path != "/" {
+		let parent = &base[..base[..base.len() - 1].rfind('/').unwrap_or(0) + 1];
+		page.push_str(&format!("<tr><td>&#11014;&#65039; <a href=\"{}\">Parent directory</a></td><td></td><td></td></tr>\n", escape(parent)));
+	}
+	for entry in &entries {
+		let (href, name, size) = if entry.is_dir {
+			(format!("{base}{}{slash}", uri::encode(&entry.name)), format!("{}/", escape(&entry.name)), String::new())
+		} else {
+			(format!("{base}{}", uri::encode(&entry.name)), escape(&entry.name), format_size(entry.size))
+		};
+		let href = escape(&href);
+		let modified = entry.modified.map(date::format).unwrap_or_default();
+		page.push_str(&format!(
+			"<tr><td>{} <a href=\"{href}\">{name}</a></td><td class=\"size\">{size}</td><td>{modified}</td></tr>\n",
+			icon(config, entry),
+		));
+	}
+	page.push_str("</table>\n</body>\n</html>\n");
+
+	return Response::content("text/html; charset=utf-8", page.into_bytes())
+		.with_header("Cache-Control", &config.cache.default)
+		.with_header("Vary", "Accept");
+}
+
+
+// Check whether a listing should be JSON, because of ?format=json or Accept: application/json
+pub fn wants_json(request: &Request) -> bool
+{
+	match parameters(request.query.as_deref()).find(|(name, _)| *name == "format") {
+		Some((_, format)) => return format == "json",
+		None => return request.headers.has_token("Accept", "application/json"),
+	}
+}
+
+
+// Create a JSON listing of a directory, whose path ends with a slash, going into directories with ?depth=N or ?recursive=true
+pub fn json(config: &Config, request: &Request, partial_path: &str, directory: &Path) -> Response
+{
+	let sort = Sort::from_query(request.query.as_deref());
+	let mut depth = 1;
+	for (name, value) in parameters(request.query.as_deref()) {
+		match (name, value) {
+			("recursive", "true" | "1") => depth = MAX_DEPTH,
+			("depth", depth_text) => depth = depth_text.parse().unwrap_or(1),
+			_ => (),
+		}
+	}
+	let depth = depth.clamp(1, MAX_DEPTH);
+
+	let entries = match read(config, partial_path, directory, sort) {
+		Ok(entries) => entries,
+		Err(_) => return Response::simple(StatusCode::NotFound),
+	};
+	let mut json = format!("{{\"path\":{},\"entries\":", json_string(partial_path));
+	let mut count = 0;
+	let complete = write_entries(config, partial_path, &entries, sort, depth, &mut count, &mut json);
+	json.push_str(&format!(",\"truncated\":{}}}\n", !complete));
+
+	return Response::content("application/json", json.into_bytes())
+		.with_header("Cache-Control", &config.cache.default)
+		.with_header("Vary", "Accept");
+}
+
+
+// Write entries as a JSON array, going into directories until the depth runs out, then tell whether everything fit
+fn write_entries(config: &Config, partial_path: &str, entries: &[Entry], sort: Sort, depth: usize, count: &mut usize, json: &mut String) -> bool
+{
+	let mut complete = true;
+	json.push('[');
+	for (index, entry) in entries.iter().enumerate() {
+		if *count >= MAX_JSON_ENTRIES {
+			complete = false;
+			break;
+		}
+		*count += 1;
+
+		if index > 0 {
+			json.push(',');
+		}
+		let (kind, content_type) = if entry.is_dir { ("directory", None) } else { ("file", Some(content_type(config, &entry.name))) };
+		json.push_str(&format!(
+			"{{\"name\":{},\"type\":\"{kind}\",\"size\":{},\"mtime\":{},\"mime\":{},\"target\":{}",
+			json_string(&entry.name),
+			entry.size,
+			entry.modified.map_or_else(|| String::from("null"), |modified| modified.to_string()),
+			content_type.map_or_else(|| String::from("null"), json_string),
+			entry.target.as_deref().map_or_else(|| String::from("null"), json_string),
+		));
+
+		if entry.is_dir && depth > 1 {
+			let child_path = format!("{partial_path}{}/", entry.name);
+			let children = read(config, &child_path, &entry.path, sort).unwrap_or_default();
+			json.push_str(",\"entries\":");
+			complete &= write_entries(config, &child_path, &children, sort, depth - 1, count, json);
+		}
+		json.push('}');
+	}
+	json.push(']');
+	return complete;
+}
+
+
+// Split a query into its name=value parameters
+fn parameters(query: Option<&str>) -> impl Iterator<Item = (&str, &str)>
+{
+	return query.unwrap_or("").split('&').filter_map(|parameter| parameter.split_once('='));
+}
+
+
+// Get the content type of a file from its extension, without reading it
+fn content_type<'a>(config: &'a Config, name: &str) -> &'a str
+{
+	let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).unwrap_or_default();
+	return mime::find(config, &extension).unwrap_or(mime::DEFAULT_TYPE);
+}
+
+
+// Pick an emoji for the kind of file
+fn icon(config: &Config, entry: &Entry) -> &'static str
+{
+	if entry.is_dir {
+		return "&#128193;";
+	}
+	let content_type = content_type(config, &entry.name);
+	return match content_type.split('/').next().unwrap_or("") {
+		"image" => "&#128444;&#65039;",
+		"audio" => "&#127925;",
+		"video" => "&#127916;",
+		"font" => "&#128292;",
+		_ if mime::is_text(content_type) => "&#128196;",
+		_ if content_type.contains("zip") || content_type.contains("compressed") || content_type.contains("tar") => "&#128230;",
+		_ => "&#128206;",
+	};
+}
+
+
+// Format a size like 1.5 KiB
+fn format_size(size: u64) -> String
+{
+	const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+	if size < 1024 {
+		return format!("{size} B");
+	}
+	let mut scaled = size as f64 / 1024.0;
+	let mut unit = 0;
+	while scaled >= 1024.0 && unit < UNITS.len() - 1 {
+		scaled /= 1024.0;
+		unit += 1;
+	}
+	return format!("{scaled:.1} {}", UNITS[unit]);
+}
+
+
+// Escape text for HTML
+pub fn escape(text: &str) -> String
+{
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			_ => escaped.push(c),
+		}
+	}
+	return escaped;
+}
+
+
+fn json_string(text: &str) -> String
+{
+	let mut quoted = String::with_capacity(text.len() + 2);
+	quoted.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'\n' => quoted.push_str("\\n"),
+			'\r' => quoted.push_str("\\r"),
+			'\t' => quoted.push_str("\\t"),
+			c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+			c => quoted.push(c),
+		}
+	}
+	quoted.push('"');
+	return quoted;
+}
@@ -0,0 +1,153 @@
+use crate::config::Config;
+
+
+// The content type of files without a known extension
+pub const DEFAULT_TYPE: &str = "application/octet-stream";
+
+// Content types by lowercase extension, sorted for binary search
+const TYPES: [(&str, &str); 118] = [
+	("3g2",         "video/3gpp2"),
+	("3gp",         "video/3gpp"),
+	("7z",          "application/x-7z-compressed"),
+	("aac",         "audio/aac"),
+	("abw",         "application/x-abiword"),
+	("aif",         "audio/aiff"),
+	("aiff",        "audio/aiff"),
+	("apng",        "image/apng"),
+	("arc",         "application/x-freearc"),
+	("atom",        "application/atom+xml"),
+	("avi",         "video/x-msvideo"),
+	("avif",        "image/avif"),
+	("azw",         "application/vnd.amazon.ebook"),
+	("bin",         "application/octet-stream"),
+	("bmp",         "image/bmp"),
+	("bz",          "application/x-bzip"),
+	("bz2",         "application/x-bzip2"),
+	("cer",         "application/pkix-cert"),
+	("cjs",         "text/javascript"),
+	("crt",         "application/x-x509-ca-cert"),
+	("csh",         "application/x-csh"),
+	("css",         "text/css"),
+	("csv",         "text/csv"),
+	("doc",         "application/msword"),
+	("docx",        "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+	("eot",         "application/vnd.ms-fontobject"),
+	("epub",        "application/epub+zip"),
+	("flac",        "audio/flac"),
+	("geojson",     "application/geo+json"),
+	("gif",         "image/gif"),
+	("glb",         "model/gltf-binary"),
+	("gltf",        "model/gltf+json"),
+	("gz",          "application/gzip"),
+	("heic",        "image/heic"),
+	("heif",        "image/heif"),
+	("htm",         "text/html"),
+	("html",        "text/html"),
+	("ico",         "image/vnd.microsoft.icon"),
+	("ics",         "text/calendar"),
+	("jar",         "application/java-archive"),
+	("jpeg",        "image/jpeg"),
+	("jpg",         "image/jpeg"),
+	("js",          "text/javascript"),
+	("json",        "application/json"),
+	("jsonld",      "application/ld+json"),
+	("jxl",         "image/jxl"),
+	("m3u8",        "application/vnd.apple.mpegurl"),
+	("m4a",         "audio/mp4"),
+	("m4v",         "video/mp4"),
+	("manifest",    "application/manifest+json"),
+	("map",         "application/json"),
+	("md",          "text/markdown"),
+	("mid",         "audio/midi"),
+	("midi",        "audio/midi"),
+	("mjs",         "text/javascript"),
+	("mkv",         "video/x-matroska"),
+	("mov",         "video/quicktime"),
+	("mp3",         "audio/mpeg"),
+	("mp4",         "video/mp4"),
+	("mpd",         "application/dash+xml"),
+	("mpeg",        "video/mpeg"),
+	("mpkg",        "application/vnd.apple.installer+xml"),
+	("odp",         "application/vnd.oasis.opendocument.presentation"),
+	("ods",         "application/vnd.oasis.opendocument.spreadsheet"),
+	("odt",         "application/vnd.oasis.opendocument.text"),
+	("oga",         "audio/ogg"),
+	("ogg",         "audio/ogg"),
+	("ogv",         "video/ogg"),
+	("ogx",         "application/ogg"),
+	("opus",        "audio/opus"),
+	("otf",         "font/otf"),
+	("pdf",         "application/pdf"),
+	("pem",         "application/x-pem-file"),
+	("php",         "application/x-httpd-php"),
+	("png",         "image/png"),
+	("ppt",         "application/vnd.ms-powerpoint"),
+	("pptx",        "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+	("ps",          "application/postscript"),
+	("rar",         "application/vnd.rar"),
+	("rss",         "application/rss+xml"),
+	("rtf",         "application/rtf"),
+	("sh",          "application/x-sh"),
+	("svg",         "image/svg+xml"),
+	("svgz",        "image/svg+xml"),
+	("swf",         "application/x-shockwave-flash"),
+	("tar",         "application/x-tar"),
+	("tif",         "image/tiff"),
+	("tiff",        "image/tiff"),
+	("toml",        "application/toml"),
+	("ts",          "video/mp2t"),
+	("tsv",         "text/tab-separated-values"),
+	("ttf",         "font/ttf"),
+	("txt",         "text/plain"),
+	("usdz",        "model/vnd.usdz+zip"),
+	("vsd",         "application/vnd.visio"),
+	("vtt",         "text/vtt"),
+	("wasm",        "application/wasm"),
+	("wav",         "audio/wav"),
+	("weba",        "audio/webm"),
+	("webm",        "video/webm"),
+	("webmanifest", "application/manifest+json"),
+	("webp",        "image/webp"),
+	("wmv",         "video/x-ms-wmv"),
+	("woff",        "font/woff"),
+	("woff2",       "font/woff2"),
+	("xhtml",       "application/xhtml+xml"),
+	("xls",         "application/vnd.ms-excel"),
+	("xlsx",        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+	("xml",         "application/xml"),
+	("xsd",         "application/xml"),
+	("xsl",         "application/xml"),
+	("xslt",        "application/xslt+xml"),
+	("xul",         "application/vnd.mozilla.xul+xml"),
+	("xz",          "application/x-xz"),
+	("yaml",        "application/yaml"),
+	("yml",         "application/yaml"),
+	("zip",         "application/zip"),
+	("zst",         "application/zstd"),
+];
+
+
+// Get the content type of a lowercase extension from the config or the built-in types
+pub fn find<'a>(config: &'a Config, extension: &str) -> Option<&'a str>
+{
+	let configured_type = config.mime.iter().find(|(configured, _)| *configured == extension).map(|(_, content_type)| content_type.as_str());
+	return configured_type.or_else(|| lookup(extension));
+}
+
+
+// Get the built-in content type of a lowercase extension
+fn lookup(extension: &str) -> Option<&'static str>
+{
+	return TYPES.binary_search_by(|(known, _)| known.cmp(&extension)).ok().map(|index| TYPES[index].1);
+}
+
+
+// Check whether a content type is text, which is sent with a charset
+pub fn is_text(content_type: &str) -> bool
+{
+	let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+	return essence.starts_with("text/")
+		|| essence.ends_with("+xml")
+		|| essence.ends_with("+json")
+		|| matches!(essence.as_str(), "application/javascript" | "application/json" | "application/xml" | "application/toml" | "application/yaml");
+}
@@ -0,0 +1,130 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::config::Proxy;
+use crate::handler;
+use crate::request;
+use crate::request::BodyLength;
+use crate::request::Request;
+use crate::response;
+use crate::response::StatusCode;
+
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+
+// Find the proxy rule for a request path, the longest prefix winning
+pub fn find<'a>(proxies: &'a [Proxy], path: &str) -> Option<&'a Proxy>
+{
+	return proxies.iter()
+		.filter(|proxy| path.strip_prefix(proxy.path.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+		.max_by_key(|proxy| proxy.path.len());
+}
+
+
+// Forward the request to the target server and copy its response back
+pub fn forward(stream: &mut TcpStream, buffer: &mut Vec<u8>, config: &Config, proxy: &Proxy, request: &Request)
+{
+	// Build a request for the target server
+	let mut upstream_target = format!("{}{}", proxy.base_path, &request.path[proxy.path.len()..]);
+	if !upstream_target.starts_with('/') {
+		upstream_target.insert(0, '/');
+	}
+	if let Some(query) = &request.query {
+		upstream_target.push('?');
+		upstream_target.push_str(query);
+	}
+	let host = proxy.authority.strip_suffix(":80").unwrap_or(&proxy.authority);
+	let mut upstream_request = format!("{} {upstream_target} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n", request.method);
+
+	// Keep the original header fields except the ones that describe this connection
+	for (name, value) in request.headers.iter() {
+		if is_hop_by_hop(name) || name.eq_ignore_ascii_case("Host") || request.headers.has_token("Connection", name) {
+			continue;
+		}
+		upstream_request.push_str(&format!("{name}: {value}\r\n"));
+	}
+	if request.body_length == BodyLength::Chunked {
+		upstream_request.push_str("Transfer-Encoding: chunked\r\n");
+	}
+	upstream_request.push_str("\r\n");
+
+	// Connect to the target server or send an error response
+	let mut upstream = match connect(&proxy.authority) {
+		Some(upstream) => upstream,
+		None => return send_error(stream, config, StatusCode::BadGateway),
+	};
+	if upstream.write_all(upstream_request.as_bytes()).is_err() {
+		return send_error(stream, config, StatusCode::BadGateway);
+	}
+
+	// Copy the request body
+	match request::copy_body(stream, buffer, request.body_length, &mut upstream) {
+		Ok(()) => (),
+		Err(error) if error.kind() == std::io::ErrorKind::InvalidData => {
+			return send_error(stream, config, StatusCode::BadRequest);
+		},
+		Err(_) => return send_error(stream, config, StatusCode::BadGateway),
+	}
+
+	// Copy the response head, telling the client that the connection closes afterwards
+	let mut upstream = BufReader::new(upstream);
+	let mut head = Vec::new();
+	loop {
+		let mut line = Vec::new();
+		if upstream.read_until(b'\n', &mut line).unwrap_or(0) == 0 || head.len() > request::MAX_HEAD_SIZE {
+			if head.is_empty() {
+				send_error(stream, config, StatusCode::BadGateway);
+			}
+			return;
+		}
+		if line == b"\r\n" || line == b"\n" {
+			head.extend_from_slice(b"Connection: close\r\n\r\n");
+			break;
+		}
+		let lowercase_line = line.to_ascii_lowercase();
+		if !lowercase_line.starts_with(b"connection:") && !lowercase_line.starts_with(b"keep-alive:") {
+			head.extend_from_slice(&line);
+		}
+	}
+	if stream.write_all(&head).is_err() {
+		return;
+	}
+
+	// Copy the rest of the response until the target server closes the connection
+	let _ = std::io::copy(&mut upstream, stream);
+}
+
+
+fn send_error(stream: &mut TcpStream, config: &Config, status_code: StatusCode)
+{
+	let _ = response::send(stream, &handler::error(config, status_code), true);
+}
+
+
+// Check for a header field that only applies to a single connection
+fn is_hop_by_hop(name: &str) -> bool
+{
+	const HOP_BY_HOP: [&str; 8] = [
+		"Connection", "Keep-Alive", "Proxy-Authenticate", "Proxy-Authorization", "TE", "Trailer", "Transfer-Encoding", "Upgrade",
+	];
+	return HOP_BY_HOP.iter().any(|hop_by_hop| hop_by_hop.eq_ignore_ascii_case(name));
+}
+
+
+fn connect(authority: &str) -> Option<TcpStream>
+{
+	for address in authority.to_socket_addrs().ok()? {
+		if let Ok(upstream) = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+			let _ = upstream.set_read_timeout(Some(READ_TIMEOUT));
+			return Some(upstream);
+		}
+	}
+	return None;
+}
@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::response::Body;
+use crate::response::Piece;
+use crate::response::Response;
+use crate::response::StatusCode;
+
+
+// The most ranges to answer, so a client can't ask for a response much larger than the file
+const MAX_RANGES: usize = 100;
+
+
+// The byte ranges a client asked for
+pub enum Ranges
+{
+	// No valid Range header, so the whole file is sent
+	All,
+	// The offset and length of each part that is inside the file
+	Satisfiable(Vec<(u64, u64)>),
+	// Valid ranges that are all outside the file
+	Unsatisfiable,
+}
+
+
+// Parse the value of a Range header for a file with the given length, ignoring a value that isn't valid
+pub fn parse(value: &str, length: u64) -> Ranges
+{
+	let Some((unit, specs)) = value.split_once('=') else {
+		return Ranges::All;
+	};
+	if !unit.trim().eq_ignore_ascii_case("bytes") {
+		return Ranges::All;
+	}
+
+	let mut count = 0;
+	let mut ranges = Vec::new();
+	for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+		count += 1;
+		if count > MAX_RANGES {
+			return Ranges::All;
+		}
+		let Some((first, last)) = spec.split_once('-') else {
+			return Ranges::All;
+		};
+
+		match (first, last) {
+			// The last bytes of the file
+			("", suffix) => match number(suffix) {
+				Some(0) => (),
+				Some(suffix) if length > 0 => ranges.push((length - suffix.min(length), suffix.min(length))),
+				Some(_) => (),
+				None => return Ranges::All,
+			},
+			// From an offset to the end of the file
+			(first, "") => match number(first) {
+				Some(first) if first < length => ranges.push((first, length - first)),
+				Some(_) => (),
+				None => return Ranges::All,
+			},
+			// From one offset to another, including the last one
+			(first, last) => match (number(first), number(last)) {
+				(Some(first), Some(last)) if first > last => return Ranges::All,
+				(Some(first), Some(last)) if first < length => ranges.push((first, last.min(length - 1) - first + 1)),
+				(Some(_), Some(_)) => (),
+				_ => return Ranges::All,
+			},
+		}
+	}
+
+	if count == 0 {
+		return Ranges::All;
+	}
+	if ranges.is_empty() {
+		return Ranges::Unsatisfiable;
+	}
+	return Ranges::Satisfiable(coalesce(ranges));
+}
+
+
+// Merge ranges that overlap or touch, so the parts never add up to more than the file, keeping the order the client asked
+// for when there is nothing to merge
+fn coalesce(ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)>
+{
+	let mut sorted = ranges.clone();
+	sorted.sort_unstable();
+	let mut merged: Vec<(u64, u64)> = Vec::with_capacity(sorted.len());
+	for (offset, length) in sorted {
+		match merged.last_mut() {
+			Some((last_offset, last_length)) if offset <= *last_offset + *last_length => {
+				*last_length = (*last_length).max(offset + length - *last_offset);
+			},
+			_ => merged.push((offset, length)),
+		}
+	}
+	if merged.len() == ranges.len() {
+		return ranges;
+	}
+	return merged;
+}
+
+
+// Create a partial response with one range of a file, or with several ranges as multipart content
+pub fn respond(content_type: &str, file: File, length: u64, ranges: &[(u64, u64)]) -> Response
+{
+	if let [(offset, part_length)] = ranges {
+		let response = Response { status_code: StatusCode::PartialContent, headers: Vec::new(), body: Body::File(file, vec![Piece::Range(*offset, *part_length)]) };
+		return response
+			.with_header("Content-Type", content_type)
+			.with_header("Content-Range", &content_range(*offset, *part_length, length));
+	}
+
+	// Separate the parts with a boundary that is very unlikely to be in the file
+	let nanoseconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+	let boundary = format!("serve-{nanoseconds:x}");
+
+	let mut pieces = Vec::new();
+	for (index, (offset, part_length)) in ranges.iter().enumerate() {
+		let separator = if index == 0 { "" } else { "\r\n" };
+		let part_head = format!("{separator}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n", content_range(*offset, *part_length, length));
+		pieces.push(Piece::Text(part_head.into_bytes()));
+		pieces.push(Piece::Range(*offset, *part_length));
+	}
+	pieces.push(Piece::Text(format!("\r\n--{boundary}--\r\n").into_bytes()));
+
+	let response = Response { status_code: StatusCode::PartialContent, headers: Vec::new(), body: Body::File(file, pieces) };
+	return response.with_header("Content-Type", &format!("multipart/byteranges; boundary={boundary}"));
+}
+
+
+fn content_range(offset: u64, part_length: u64, length: u64) -> String
+{
+	return format!("bytes {offset}-{}/{length}", offset + part_length - 1);
+}
+
+
+// Parse a number that only has digits
+fn number(text: &str) -> Option<u64>
+{
+	if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+		return None;
+	}
+	return text.parse().ok();
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::Ranges;
+
+
+	// Describe ranges in a way that is easy to compare
+	fn describe(ranges: Ranges) -> String
+	{
+		return match ranges {
+			Ranges::All => String::from("all"),
+			Ranges::Satisfiable(ranges) => format!("{ranges:?}"),
+			Ranges::Unsatisfiable => String::from("unsatisfiable"),
+		};
+	}
+
+
+	#[test]
+	fn parse()
+	{
+		let cases = [
+			("bytes=0-9", "[(0, 10)]"),
+			("bytes=90-", "[(90, 10)]"),
+			("bytes=-10", "[(90, 10)]"),
+			("bytes=-1000", "[(0, 100)]"),
+			("bytes=50-1000", "[(50, 50)]"),
+			("BYTES = 0-0", "[(0, 1)]"),
+			("bytes=0-0, 99-99", "[(0, 1), (99, 1)]"),
+			("bytes=50-59, 0-9", "[(50, 10), (0, 10)]"),
+			("bytes=0-9, 5-19, 30-39", "[(0, 20), (30, 10)]"),
+			("bytes=10-19, 0-9", "[(0, 20)]"),
+			("bytes=0-, 0-, 0-", "[(0, 100)]"),
+			("bytes=100-, 0-4", "[(0, 5)]"),
+			("bytes=0-0,,", "[(0, 1)]"),
+			("bytes=100-199", "unsatisfiable"),
+			("bytes=-0", "unsatisfiable"),
+			("items=0-9", "all"),
+			("bytes=9-0", "all"),
+			("bytes=0-9, x", "all"),
+			("bytes=+1-9", "all"),
+			("bytes=", "all"),
+			("0-9", "all"),
+		];
+		for (value, expected) in cases {
+			assert_eq!(describe(super::parse(value, 100)), expected, "{value}");
+		}
+	}
+
+
+	#[test]
+	fn edge_cases()
+	{
+		// Overlapping ranges can't add up to more than the file
+		let value = format!("bytes={}", vec!["0-99"; 100].join(","));
+		assert_eq!(describe(super::parse(&value, 100)), "[(0, 100)]");
+
+		let value = format!("bytes={}", vec!["0-0"; 101].join(","));
+		assert_eq!(describe(super::parse(&value, 100)), "all");
+
+		assert_eq!(describe(super::parse("bytes=0-9", 0)), "unsatisfiable");
+		assert_eq!(describe(super::parse("bytes=-5", 0)), "unsatisfiable");
+		assert_eq!(describe(super::parse("bytes=0-18446744073709551615", u64::MAX)), "[(0, 18446744073709551615)]");
+	}
+}
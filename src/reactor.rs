@@ -0,0 +1,560 @@
+// A single-threaded server that handles every connection with non-blocking sockets and epoll
+
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::config::Proxy;
+use crate::handler;
+use crate::handler::Route;
+use crate::proxy;
+use crate::request;
+use crate::request::BodyLength;
+use crate::request::Request;
+use crate::response;
+use crate::response::Body;
+use crate::response::Piece;
+use crate::response::Response;
+use crate::response::StatusCode;
+use crate::server::IDLE_TIMEOUT;
+
+
+// The epoll token of the listener, which can't be a connection index
+const LISTENER: u64 = u64::MAX;
+
+const MAX_EVENTS: usize = 256;
+
+const READ_SIZE: usize = 16384;
+
+// Stop reading pipelined requests while this much is waiting to be parsed
+const MAX_BUFFERED: usize = 2 * request::MAX_HEAD_SIZE;
+
+
+// The state of a connection between events
+struct Connection
+{
+	stream: TcpStream,
+	generation: u64,
+	// Received bytes that haven't been parsed yet
+	input: Vec<u8>,
+	// Bytes of a request body that still have to be dropped
+	discard: u64,
+	// Response bytes that haven't been written yet, followed by the rest of a file
+	output: Vec<u8>,
+	written: usize,
+	file: Option<(File, VecDeque<Piece>)>,
+	keep_alive: bool,
+	// Whether the client stopped sending
+	finished: bool,
+	// Whether part of the next request has arrived
+	started: bool,
+	deadline: Instant,
+	scheduled: Instant,
+	events: u32,
+}
+
+
+// A request to forward on a proxy thread, with its connection and the bytes received after its head
+struct ProxyJob
+{
+	stream: TcpStream,
+	input: Vec<u8>,
+	proxy: &'static Proxy,
+	request: Request,
+}
+
+
+// What to do with a connection after handling an event
+enum Next
+{
+	Wait,
+	Close,
+	Proxy(&'static Proxy, Request),
+}
+
+
+struct Reactor
+{
+	config: &'static Config,
+	epoll: OwnedFd,
+	// Connections by token, reusing the slots of closed connections
+	connections: Vec<Option<Connection>>,
+	free_slots: Vec<usize>,
+	open: usize,
+	// Connections handed to proxy threads, which count toward the limit until they're closed
+	proxied: Arc<AtomicUsize>,
+	// The queue of the proxy threads, which are started for the first proxied request
+	proxy_jobs: Option<Sender<ProxyJob>>,
+	timers: BinaryHeap<Reverse<(Instant, usize, u64)>>,
+	next_generation: u64,
+}
+
+
+// Accept connections and handle their events until the program exits
+pub fn run(config: &'static Config, listener: &TcpListener) -> std::io::Result<()>
+{
+	listener.set_nonblocking(true)?;
+
+	let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+	if epoll < 0 {
+		return Err(std::io::Error::last_os_error());
+	}
+	let mut reactor = Reactor {
+		config,
+		epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+		connections: Vec::new(),
+		free_slots: Vec::new(),
+		open: 0,
+		proxied: Arc::new(AtomicUsize::new(0)),
+		proxy_jobs: None,
+		timers: BinaryHeap::new(),
+		next_generation: 0,
+	};
+	reactor.control(libc::EPOLL_CTL_ADD, listener.as_raw_fd(), libc::EPOLLIN as u32, LISTENER)?;
+
+	let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+	loop {
+		let timeout = reactor.next_timeout();
+		let count = unsafe { libc::epoll_wait(reactor.epoll.as_raw_fd(), events.as_mut_ptr(), MAX_EVENTS as libc::c_int, timeout) };
+		if count < 0 {
+			let error = std::io::Error::last_os_error();
+			if error.kind() == ErrorKind::Interrupted {
+				continue;
+			}
+			return Err(error);
+		}
+
+		for event in &events[..count as usize] {
+			let (token, flags) = (event.u64, event.events);
+			if token == LISTENER {
+				reactor.accept(listener);
+			} else {
+				reactor.handle_event(token as usize, flags);
+			}
+		}
+
+		reactor.expire_timers();
+	}
+}
+
+
+impl Reactor
+{
+	fn control(&self, operation: libc::c_int, fd: libc::c_int, events: u32, token: u64) -> std::io::Result<()>
+	{
+		let mut event = libc::epoll_event { events, u64: token };
+		if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), operation, fd, &mut event) } < 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		return Ok(());
+	}
+
+
+	// Accept every waiting connection
+	fn accept(&mut self, listener: &TcpListener)
+	{
+		loop {
+			let stream = match listener.accept() {
+				Ok((stream, _)) => stream,
+				Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+				Err(_) => return,
+			};
+
+			// Turn the client away when there are too many connections
+			if self.open + self.proxied.load(Ordering::SeqCst) >= self.config.max_connections {
+				let response = handler::error(self.config, StatusCode::ServiceUnavailable).with_header("Retry-After", "1");
+				let _ = stream.set_nonblocking(true);
+				write_error(&stream, &response);
+				continue;
+			}
+			if stream.set_nonblocking(true).is_err() {
+				continue;
+			}
+			let _ = stream.set_nodelay(true);
+
+			let deadline = Instant::now() + IDLE_TIMEOUT;
+			let events = (libc::EPOLLIN | libc::EPOLLRDHUP) as u32;
+			let connection = Connection {
+				stream,
+				generation: self.next_generation,
+				input: Vec::new(),
+				discard: 0,
+				output: Vec::new(),
+				written: 0,
+				file: None,
+				keep_alive: true,
+				finished: false,
+				started: false,
+				deadline,
+				scheduled: deadline,
+				events,
+			};
+			self.next_generation += 1;
+
+			let token = match self.free_slots.pop() {
+				Some(token) => token,
+				None => {
+					self.connections.push(None);
+					self.connections.len() - 1
+				},
+			};
+			if self.control(libc::EPOLL_CTL_ADD, connection.stream.as_raw_fd(), events, token as u64).is_err() {
+				self.free_slots.push(token);
+				continue;
+			}
+			self.timers.push(Reverse((deadline, token, connection.generation)));
+			self.connections[token] = Some(connection);
+			self.open += 1;
+		}
+	}
+
+
+	// Read, parse, and write as far as possible without blocking
+	fn handle_event(&mut self, token: usize, flags: u32)
+	{
+		let config = self.config;
+		let Some(connection) = self.connections.get_mut(token).and_then(Option::as_mut) else {
+			return;
+		};
+
+		let readable = (libc::EPOLLIN | libc::EPOLLRDHUP | libc::EPOLLHUP | libc::EPOLLERR) as u32;
+		let next = if flags & readable != 0 && !connection.receive() {
+			Next::Close
+		} else {
+			connection.advance(config)
+		};
+
+		match next {
+			Next::Wait => self.update(token),
+			Next::Close => self.close(token),
+			Next::Proxy(proxy, request) => self.hand_off(token, proxy, request),
+		}
+	}
+
+
+	// Change the events to wait for and schedule the deadline
+	fn update(&mut self, token: usize)
+	{
+		let Some(connection) = self.connections[token].as_mut() else {
+			return;
+		};
+
+		let mut events = 0;
+		if !connection.finished && connection.input.len() < MAX_BUFFERED {
+			events |= (libc::EPOLLIN | libc::EPOLLRDHUP) as u32;
+		}
+		if !connection.output.is_empty() {
+			events |= libc::EPOLLOUT as u32;
+		}
+
+		if connection.deadline < connection.scheduled {
+			connection.scheduled = connection.deadline;
+			self.timers.push(Reverse((connection.deadline, token, connection.generation)));
+		}
+
+		if events != connection.events {
+			connection.events = events;
+			let fd = connection.stream.as_raw_fd();
+			if self.control(libc::EPOLL_CTL_MOD, fd, events, token as u64).is_err() {
+				self.close(token);
+			}
+		}
+	}
+
+
+	fn close(&mut self, token: usize)
+	{
+		if let Some(connection) = self.connections[token].take() {
+			let _ = self.control(libc::EPOLL_CTL_DEL, connection.stream.as_raw_fd(), 0, 0);
+			self.free_slots.push(token);
+			self.open -= 1;
+		}
+	}
+
+
+	// Forward a request on one of the proxy threads, since the proxy uses blocking sockets, queuing it while every thread
+	// is busy
+	fn hand_off(&mut self, token: usize, proxy: &'static Proxy, request: Request)
+	{
+		let Some(connection) = self.connections[token].take() else {
+			return;
+		};
+		let _ = self.control(libc::EPOLL_CTL_DEL, connection.stream.as_raw_fd(), 0, 0);
+		self.free_slots.push(token);
+		self.open -= 1;
+		self.proxied.fetch_add(1, Ordering::SeqCst);
+
+		let (config, proxied) = (self.config, &self.proxied);
+		let proxy_jobs = self.proxy_jobs.get_or_insert_with(|| start_proxy_threads(config, proxied));
+		let job = ProxyJob { stream: connection.stream, input: connection.input, proxy, request };
+		if proxy_jobs.send(job).is_err() {
+			self.proxied.fetch_sub(1, Ordering::SeqCst);
+		}
+	}
+
+
+	// Get how long epoll can wait before the next deadline, in milliseconds
+	fn next_timeout(&self) -> libc::c_int
+	{
+		let Some(Reverse((deadline, _, _))) = self.timers.peek() else {
+			return -1;
+		};
+		let remaining = deadline.saturating_duration_since(Instant::now());
+		return remaining.as_millis().saturating_add(1).min(libc::c_int::MAX as u128) as libc::c_int;
+	}
+
+
+	// Close the connections whose deadlines have passed, rescheduling the ones that moved
+	fn expire_timers(&mut self)
+	{
+		let now = Instant::now();
+		while let Some(Reverse((deadline, token, generation))) = self.timers.peek().copied() {
+			if deadline > now {
+				return;
+			}
+			self.timers.pop();
+
+			let Some(connection) = self.connections[token].as_mut().filter(|connection| connection.generation == generation) else {
+				continue;
+			};
+			if deadline != connection.scheduled {
+				continue;
+			}
+			if connection.deadline > now {
+				connection.scheduled = connection.deadline;
+				self.timers.push(Reverse((connection.deadline, token, generation)));
+				continue;
+			}
+
+			// Tell a client that is too slow to send its request, then close the connection
+			if connection.started && connection.output.is_empty() {
+				let response = handler::error(self.config, StatusCode::RequestTimeout);
+				write_error(&connection.stream, &response);
+			}
+			self.close(token);
+		}
+	}
+}
+
+
+// Start as many proxy threads as workers of the thread pool, getting the queue they take requests from
+fn start_proxy_threads(config: &'static Config, proxied: &Arc<AtomicUsize>) -> Sender<ProxyJob>
+{
+	let (sender, receiver) = mpsc::channel();
+	let receiver = Arc::new(Mutex::new(receiver));
+	for _ in 0..config.workers {
+		let receiver = Arc::clone(&receiver);
+		let proxied = Arc::clone(proxied);
+		let _ = std::thread::Builder::new().spawn(move || forward_jobs(config, &receiver, &proxied));
+	}
+	return sender;
+}
+
+
+// Forward requests from the queue one at a time, closing each connection afterwards
+fn forward_jobs(config: &Config, receiver: &Mutex<Receiver<ProxyJob>>, proxied: &AtomicUsize)
+{
+	loop {
+		let mut job = match receiver.lock().map(|receiver| receiver.recv()) {
+			Ok(Ok(job)) => job,
+			_ => return,
+		};
+
+		if job.stream.set_nonblocking(false).is_ok() {
+			let _ = job.stream.set_read_timeout(Some(config.timeout));
+			let _ = job.stream.set_write_timeout(Some(config.timeout));
+			// Keep the thread alive even if forwarding panics
+			let _ = std::panic::catch_unwind(AssertUnwindSafe(|| proxy::forward(&mut job.stream, &mut job.input, config, job.proxy, &job.request)));
+		}
+		drop(job);
+		proxied.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+
+// Write an error response at once, as far as the socket takes it, before the connection is closed
+fn write_error(mut stream: &TcpStream, response: &Response)
+{
+	let mut output = response.head();
+	if let Body::Bytes(bytes) = &response.body {
+		output.extend_from_slice(bytes);
+	}
+	let _ = stream.write(&output);
+}
+
+
+impl Connection
+{
+	// Read what is available, dropping request body bytes, then tell whether the connection is still usable
+	fn receive(&mut self) -> bool
+	{
+		let mut chunk = [0; READ_SIZE];
+		while !self.finished && self.input.len() < MAX_BUFFERED {
+			match self.stream.read(&mut chunk) {
+				Ok(0) => {
+					self.finished = true;
+					self.keep_alive = false;
+				},
+				Ok(length) => {
+					let discarded = usize::try_from(self.discard).unwrap_or(usize::MAX).min(length);
+					self.discard -= discarded as u64;
+					self.input.extend_from_slice(&chunk[discarded..length]);
+				},
+				Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+				Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+				Err(_) => return false,
+			}
+		}
+		return true;
+	}
+
+
+	// Write pending output and handle buffered requests until waiting is necessary
+	fn advance(&mut self, config: &'static Config) -> Next
+	{
+		loop {
+			// Write the current response
+			if !self.output.is_empty() {
+				match self.send() {
+					Ok(true) => (),
+					Ok(false) => {
+						self.deadline = Instant::now() + config.timeout;
+						return Next::Wait;
+					},
+					Err(_) => return Next::Close,
+				}
+				if !self.keep_alive {
+					return Next::Close;
+				}
+				self.deadline = Instant::now() + IDLE_TIMEOUT;
+			}
+
+			// Drop the rest of the previous request body
+			let discarded = usize::try_from(self.discard).unwrap_or(usize::MAX).min(self.input.len());
+			self.input.drain(..discarded);
+			self.discard -= discarded as u64;
+			if self.discard > 0 {
+				return if self.finished { Next::Close } else { Next::Wait };
+			}
+
+			// Parse the next request or wait for the rest of it
+			let mut request = match request::parse_buffered(&mut self.input) {
+				Ok(Some(request)) => request,
+				Ok(None) => {
+					if self.finished {
+						return Next::Close;
+					}
+					if !self.started && request::has_started(&self.input) {
+						self.started = true;
+						self.deadline = Instant::now() + config.timeout;
+					}
+					return Next::Wait;
+				},
+				Err(error) => {
+					let Some(status_code) = error.status_code() else {
+						return Next::Close;
+					};
+					self.keep_alive = false;
+					self.queue(handler::error(config, status_code), true, config);
+					continue;
+				},
+			};
+			self.started = false;
+
+			// Forward the request to a configured server or queue a response
+			let mut response = match handler::route(config, &mut request) {
+				Route::Respond(response) => response,
+				Route::Proxy(proxy) => return Next::Proxy(proxy, request),
+			};
+
+			// Drop the body so the next request can be read, unless it is too big or chunked
+			self.keep_alive = request.keep_alive() && !self.finished && match request.body_length {
+				BodyLength::Length(length) if length <= request::MAX_DISCARDED_BODY_SIZE => {
+					self.discard = length;
+					true
+				},
+				_ => false,
+			};
+			handler::set_connection(&mut response, &request, self.keep_alive);
+			self.queue(response, request.method != "HEAD", config);
+		}
+	}
+
+
+	fn queue(&mut self, response: Response, send_body: bool, config: &Config)
+	{
+		self.output = response.head();
+		if send_body {
+			match response.body {
+				Body::Bytes(bytes) => self.output.extend_from_slice(&bytes),
+				Body::File(file, pieces) => self.file = Some((file, VecDeque::from(pieces))),
+			}
+		}
+		self.written = 0;
+		self.deadline = Instant::now() + config.timeout;
+	}
+
+
+	// Write as much output and file content as possible, then tell whether all of it was written
+	fn send(&mut self) -> std::io::Result<bool>
+	{
+		while self.written < self.output.len() {
+			match self.stream.write(&self.output[self.written..]) {
+				Ok(0) => return Err(ErrorKind::WriteZero.into()),
+				Ok(length) => self.written += length,
+				Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(false),
+				Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+				Err(error) => return Err(error),
+			}
+		}
+		while let Some((file, pieces)) = &mut self.file {
+			let result = match pieces.front_mut() {
+				None => {
+					self.file = None;
+					break;
+				},
+				Some(piece) if piece.len() == 0 => {
+					pieces.pop_front();
+					continue;
+				},
+				Some(Piece::Text(text)) => self.stream.write(text).inspect(|written| {
+					text.drain(..*written);
+				}),
+				Some(Piece::Range(offset, length)) => response::write_file(&self.stream, file, *offset, *length).inspect(|written| {
+					*offset += *written as u64;
+					*length -= *written as u64;
+				}),
+			};
+			match result {
+				Ok(0) => return Err(ErrorKind::WriteZero.into()),
+				Ok(_) => (),
+				Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(false),
+				Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+				Err(error) => return Err(error),
+			}
+		}
+		self.output.clear();
+		self.written = 0;
+		return Ok(true);
+	}
+}
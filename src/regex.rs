@@ -0,0 +1,541 @@
+// A small regular expression engine for rewrite rules, which compiles a pattern to a program for a Pike VM, so matching
+// runs every possible path in step over the text, taking time proportional to the text times the pattern, without
+// recursion or backtracking
+//
+// It supports literals, ., [classes], \d \w \s and their negations, ^ and $, (groups), (?:groups), |, and the
+// quantifiers * + ? {n} {n,} {n,m}, which are lazy when followed by ?
+
+
+// The most instructions a pattern may compile to, which counts like {1000} can multiply
+const MAX_PROGRAM_SIZE: usize = 10000;
+
+
+pub struct Regex
+{
+	program: Vec<Instruction>,
+	groups: usize,
+}
+
+
+enum Node
+{
+	Literal(char),
+	Any,
+	Class(Vec<ClassItem>, bool),
+	Start,
+	End,
+	// A group with its capture index, or none for (?:...)
+	Group(Box<Node>, Option<usize>),
+	Sequence(Vec<Node>),
+	Alternation(Vec<Node>),
+	Repeat(Box<Node>, Repetition),
+}
+
+
+#[derive(Clone)]
+enum ClassItem
+{
+	Range(char, char),
+	Digit(bool),
+	Word(bool),
+	Space(bool),
+}
+
+
+#[derive(Clone, Copy)]
+struct Repetition
+{
+	min: usize,
+	max: Option<usize>,
+	greedy: bool,
+}
+
+
+enum Instruction
+{
+	Literal(char),
+	Any,
+	Class(Vec<ClassItem>, bool),
+	Start,
+	End,
+	// Remember the position in a capture slot, where group n starts in slot 2n and ends in slot 2n + 1
+	Save(usize),
+	// Continue at both instructions, preferring the first
+	Split(usize, usize),
+	Jump(usize),
+	Match,
+}
+
+
+// A path through the program waiting at an instruction that reads a character, with its capture slots
+struct Thread
+{
+	instruction: usize,
+	slots: Vec<Option<usize>>,
+}
+
+
+struct Parser<'a>
+{
+	chars: &'a [char],
+	position: usize,
+	groups: usize,
+}
+
+
+impl Regex
+{
+	pub fn new(pattern: &str) -> Result<Regex, String>
+	{
+		let chars: Vec<char> = pattern.chars().collect();
+		let mut parser = Parser { chars: &chars, position: 0, groups: 0 };
+		let node = parser.alternation()?;
+		if parser.position < chars.len() {
+			return Err(format!("unmatched ')' at {}", parser.position + 1));
+		}
+
+		let mut program = vec![Instruction::Save(0)];
+		compile(&node, &mut program)?;
+		program.push(Instruction::Save(1));
+		program.push(Instruction::Match);
+		return Ok(Regex { program, groups: parser.groups });
+	}
+
+
+	// Find the first match in a text, preferring the leftmost one like a backtracking engine, getting the whole match and
+	// every group, which may not have matched
+	pub fn captures(&self, text: &str) -> Option<Vec<Option<String>>>
+	{
+		let chars: Vec<char> = text.chars().collect();
+		let mut threads = Vec::new();
+		let mut queued = vec![false; self.program.len()];
+		let mut matched = None;
+
+		for position in 0..=chars.len() {
+			// Start a new match here, after the threads that started earlier, until one matches
+			if matched.is_none() {
+				self.add_thread(&mut threads, &mut queued, 0, position, chars.len(), vec![None; 2 * (self.groups + 1)]);
+			}
+			if threads.is_empty() && matched.is_some() {
+				break;
+			}
+
+			let mut next_threads = Vec::new();
+			queued.fill(false);
+			for thread in threads.drain(..) {
+				let c = chars.get(position);
+				let advances = match &self.program[thread.instruction] {
+					Instruction::Literal(literal) => c == Some(literal),
+					Instruction::Any => c.is_some(),
+					Instruction::Class(items, negated) => c.is_some_and(|c| items.iter().any(|item| item.contains(*c)) != *negated),
+					// Threads after a match are less preferred, so they stop
+					Instruction::Match => {
+						matched = Some(thread.slots);
+						break;
+					},
+					_ => false,
+				};
+				if advances {
+					self.add_thread(&mut next_threads, &mut queued, thread.instruction + 1, position + 1, chars.len(), thread.slots);
+				}
+			}
+			threads = next_threads;
+		}
+
+		let slots = matched?;
+		let text = |group: usize| match (slots[2 * group], slots[2 * group + 1]) {
+			(Some(start), Some(end)) => Some(chars[start..end].iter().collect()),
+			_ => None,
+		};
+		return Some((0..=self.groups).map(text).collect());
+	}
+
+
+	// Follow jumps, splits, saves, and anchors from an instruction without reading, queuing the threads that end up at
+	// instructions that read, in order of preference
+	fn add_thread(&self, threads: &mut Vec<Thread>, queued: &mut [bool], instruction: usize, position: usize, length: usize, slots: Vec<Option<usize>>)
+	{
+		let mut stack = vec![(instruction, slots)];
+		while let Some((instruction, mut slots)) = stack.pop() {
+			if queued[instruction] {
+				continue;
+			}
+			queued[instruction] = true;
+			match self.program[instruction] {
+				Instruction::Jump(target) => stack.push((target, slots)),
+				Instruction::Split(first, second) => {
+					stack.push((second, slots.clone()));
+					stack.push((first, slots));
+				},
+				Instruction::Save(slot) => {
+					slots[slot] = Some(position);
+					stack.push((instruction + 1, slots));
+				},
+				Instruction::Start => if position == 0 {
+					stack.push((instruction + 1, slots));
+				},
+				Instruction::End => if position == length {
+					stack.push((instruction + 1, slots));
+				},
+				_ => threads.push(Thread { instruction, slots }),
+			}
+		}
+	}
+}
+
+
+// Add the instructions of a node to a program
+fn compile(node: &Node, program: &mut Vec<Instruction>) -> Result<(), String>
+{
+	if program.len() > MAX_PROGRAM_SIZE {
+		return Err(String::from("pattern too large"));
+	}
+	match node {
+		Node::Literal(c) => program.push(Instruction::Literal(*c)),
+		Node::Any => program.push(Instruction::Any),
+		Node::Class(items, negated) => program.push(Instruction::Class(items.clone(), *negated)),
+		Node::Start => program.push(Instruction::Start),
+		Node::End => program.push(Instruction::End),
+		Node::Group(node, None) => compile(node, program)?,
+		Node::Group(node, Some(index)) => {
+			program.push(Instruction::Save(2 * index));
+			compile(node, program)?;
+			program.push(Instruction::Save(2 * index + 1));
+		},
+		Node::Sequence(nodes) => {
+			for node in nodes {
+				compile(node, program)?;
+			}
+		},
+		Node::Alternation(nodes) => {
+			let mut jumps = Vec::new();
+			for (index, node) in nodes.iter().enumerate() {
+				if index == nodes.len() - 1 {
+					compile(node, program)?;
+					break;
+				}
+				let split = program.len();
+				program.push(Instruction::Split(split + 1, 0));
+				compile(node, program)?;
+				jumps.push(program.len());
+				program.push(Instruction::Jump(0));
+				program[split] = Instruction::Split(split + 1, program.len());
+			}
+			for jump in jumps {
+				program[jump] = Instruction::Jump(program.len());
+			}
+		},
+		Node::Repeat(node, repetition) => {
+			for _ in 0..repetition.min {
+				compile(node, program)?;
+			}
+			match repetition.max {
+				// Loop back to a split between another repetition and the rest
+				None => {
+					let split = program.len();
+					program.push(Instruction::Jump(0));
+					compile(node, program)?;
+					program.push(Instruction::Jump(split));
+					program[split] = split_instruction(split + 1, program.len(), repetition.greedy);
+				},
+				// Nest optional repetitions, each skipping to the end
+				Some(max) => {
+					let mut splits = Vec::new();
+					for _ in repetition.min..max {
+						splits.push(program.len());
+						program.push(Instruction::Jump(0));
+						compile(node, program)?;
+						if program.len() > MAX_PROGRAM_SIZE {
+							return Err(String::from("pattern too large"));
+						}
+					}
+					for split in splits {
+						program[split] = split_instruction(split + 1, program.len(), repetition.greedy);
+					}
+				},
+			}
+		},
+	}
+	return Ok(());
+}
+
+
+// Split between repeating and going on, preferring to repeat when greedy
+fn split_instruction(repeat: usize, skip: usize, greedy: bool) -> Instruction
+{
+	if greedy {
+		return Instruction::Split(repeat, skip);
+	}
+	return Instruction::Split(skip, repeat);
+}
+
+
+impl ClassItem
+{
+	fn contains(&self, c: char) -> bool
+	{
+		return match *self {
+			ClassItem::Range(first, last) => (first..=last).contains(&c),
+			ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+			ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+			ClassItem::Space(negated) => c.is_whitespace() != negated,
+		};
+	}
+}
+
+
+impl Parser<'_>
+{
+	fn alternation(&mut self) -> Result<Node, String>
+	{
+		let mut alternatives = vec![self.sequence()?];
+		while self.eat('|') {
+			alternatives.push(self.sequence()?);
+		}
+		if alternatives.len() == 1 {
+			return Ok(alternatives.remove(0));
+		}
+		return Ok(Node::Alternation(alternatives));
+	}
+
+
+	fn sequence(&mut self) -> Result<Node, String>
+	{
+		let mut nodes = Vec::new();
+		while let Some(&c) = self.chars.get(self.position) {
+			if c == '|' || c == ')' {
+				break;
+			}
+			let atom = self.atom()?;
+			nodes.push(self.quantifier(atom)?);
+		}
+		return Ok(Node::Sequence(nodes));
+	}
+
+
+	fn atom(&mut self) -> Result<Node, String>
+	{
+		let c = self.chars[self.position];
+		self.position += 1;
+		return match c {
+			'.' => Ok(Node::Any),
+			'^' => Ok(Node::Start),
+			'$' => Ok(Node::End),
+			'(' => {
+				let index = if self.eat('?') {
+					if !self.eat(':') {
+						return Err(format!("unsupported group at {}, expected (?:...)", self.position - 1));
+					}
+					None
+				} else {
+					self.groups += 1;
+					Some(self.groups)
+				};
+				let node = self.alternation()?;
+				if !self.eat(')') {
+					return Err(String::from("missing ')'"));
+				}
+				Ok(Node::Group(Box::new(node), index))
+			},
+			'[' => self.class(),
+			'\\' => match self.escape()? {
+				ClassItem::Range(c, _) => Ok(Node::Literal(c)),
+				item => Ok(Node::Class(vec![item], false)),
+			},
+			'*' | '+' | '?' => Err(format!("nothing to repeat at {}", self.position)),
+			c => Ok(Node::Literal(c)),
+		};
+	}
+
+
+	fn quantifier(&mut self, atom: Node) -> Result<Node, String>
+	{
+		let start = self.position;
+		let (min, max) = match self.chars.get(self.position) {
+			Some('*') => (0, None),
+			Some('+') => (1, None),
+			Some('?') => (0, Some(1)),
+			// A brace that doesn't start a count is a literal
+			Some('{') => match self.count() {
+				Some(count) => count,
+				None => return Ok(atom),
+			},
+			_ => return Ok(atom),
+		};
+		if matches!(atom, Node::Start | Node::End) {
+			return Err(format!("nothing to repeat at {}", start + 1));
+		}
+		if max.is_some_and(|max| max < min) {
+			return Err(format!("invalid count at {}", start + 1));
+		}
+		if self.chars[start] != '{' {
+			self.position += 1;
+		}
+		let greedy = !self.eat('?');
+		return Ok(Node::Repeat(Box::new(atom), Repetition { min, max, greedy }));
+	}
+
+
+	// Read {n}, {n,}, or {n,m}, or nothing without moving
+	fn count(&mut self) -> Option<(usize, Option<usize>)>
+	{
+		let rest: String = self.chars[self.position + 1..].iter().take_while(|c| **c != '}').collect();
+		if self.chars.get(self.position + 1 + rest.chars().count()) != Some(&'}') {
+			return None;
+		}
+		let count = match rest.split_once(',') {
+			Some((min, "")) => (min.parse().ok()?, None),
+			Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+			None => (rest.parse().ok()?, Some(rest.parse().ok()?)),
+		};
+		self.position += rest.chars().count() + 2;
+		return Some(count);
+	}
+
+
+	fn class(&mut self) -> Result<Node, String>
+	{
+		let negated = self.eat('^');
+		let mut items = Vec::new();
+		loop {
+			let Some(&c) = self.chars.get(self.position) else {
+				return Err(String::from("missing ']'"));
+			};
+			self.position += 1;
+
+			// A ] right after [ or [^ is a literal
+			let item = match c {
+				']' if !items.is_empty() => return Ok(Node::Class(items, negated)),
+				'\\' => self.escape()?,
+				c => ClassItem::Range(c, c),
+			};
+			match (item, self.chars.get(self.position), self.chars.get(self.position + 1)) {
+				(ClassItem::Range(first, _), Some('-'), Some(&last)) if last != ']' => {
+					self.position += 2;
+					let last = match last {
+						'\\' => match self.escape()? {
+							ClassItem::Range(last, _) => last,
+							_ => return Err(format!("invalid range at {}", self.position)),
+						},
+						last => last,
+					};
+					if last < first {
+						return Err(format!("invalid range at {}", self.position));
+					}
+					items.push(ClassItem::Range(first, last));
+				},
+				(item, _, _) => items.push(item),
+			}
+		}
+	}
+
+
+	// Read the character after a backslash
+	fn escape(&mut self) -> Result<ClassItem, String>
+	{
+		let Some(&c) = self.chars.get(self.position) else {
+			return Err(String::from("trailing backslash"));
+		};
+		self.position += 1;
+		return Ok(match c {
+			'd' => ClassItem::Digit(false),
+			'D' => ClassItem::Digit(true),
+			'w' => ClassItem::Word(false),
+			'W' => ClassItem::Word(true),
+			's' => ClassItem::Space(false),
+			'S' => ClassItem::Space(true),
+			'n' => ClassItem::Range('\n', '\n'),
+			'r' => ClassItem::Range('\r', '\r'),
+			't' => ClassItem::Range('\t', '\t'),
+			c if c.is_ascii_alphanumeric() => return Err(format!("unsupported escape '\\{c}'")),
+			c => ClassItem::Range(c, c),
+		});
+	}
+
+
+	fn eat(&mut self, c: char) -> bool
+	{
+		if self.chars.get(self.position) == Some(&c) {
+			self.position += 1;
+			return true;
+		}
+		return false;
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::Regex;
+
+
+	// A pattern, a text, and the groups it should capture
+	type Case = (&'static str, &'static str, Option<&'static [Option<&'static str>]>);
+
+
+	#[test]
+	fn captures()
+	{
+		let cases: [Case; 16] = [
+			("abc", "xabcx", Some(&[Some("abc")])),
+			("^abc$", "xabc", None),
+			("$", "abc", Some(&[Some("")])),
+			("^/blog/(\\d+)/(.*)$", "/blog/2024/post", Some(&[Some("/blog/2024/post"), Some("2024"), Some("post")])),
+			("^/(a|ab)(c|bcd)$", "/abcd", Some(&[Some("/abcd"), Some("a"), Some("bcd")])),
+			("a(x)?b", "ab", Some(&[Some("ab"), None])),
+			("(a*)(a*)", "aaa", Some(&[Some("aaa"), Some("aaa"), Some("")])),
+			("(a*?)(a*)", "aaa", Some(&[Some("aaa"), Some(""), Some("aaa")])),
+			("<(.+)>", "<a><b>", Some(&[Some("<a><b>"), Some("a><b")])),
+			("<(.+?)>", "<a><b>", Some(&[Some("<a>"), Some("a")])),
+			("^a{2,3}$", "aaaa", None),
+			("^(?:ab){2}(c{1,})$", "ababcc", Some(&[Some("ababcc"), Some("cc")])),
+			("^[^/]+\\.(?:jpe?g|png)$", "photo.jpeg", Some(&[Some("photo.jpeg")])),
+			("^[]a-c]+$", "]ab", Some(&[Some("]ab")])),
+			("^\\w+\\s\\W$", "word !", Some(&[Some("word !")])),
+			("(a*)*b", "aab", Some(&[Some("aab"), Some("aa")])),
+		];
+		for (pattern, text, expected) in cases {
+			let regex = Regex::new(pattern).unwrap();
+			let expected = expected.map(|groups| groups.iter().map(|group| group.map(String::from)).collect::<Vec<_>>());
+			assert_eq!(regex.captures(text), expected, "{pattern} on {text}");
+		}
+	}
+
+
+	#[test]
+	fn invalid_patterns()
+	{
+		let cases = [
+			("(a", "missing ')'"),
+			("a)", "unmatched ')'"),
+			("*a", "nothing to repeat"),
+			("[a", "missing ']'"),
+			("[z-a]", "invalid range"),
+			("a{3,1}", "invalid count"),
+			("\\q", "unsupported escape"),
+			("(?:a{1000}){1000}", "pattern too large"),
+		];
+		for (pattern, error) in cases {
+			match Regex::new(pattern) {
+				Ok(_) => panic!("{pattern} compiled"),
+				Err(message) => assert!(message.contains(error), "{pattern}: {message}"),
+			}
+		}
+	}
+
+
+	#[test]
+	fn long_input()
+	{
+		let text = "a".repeat(30_000);
+		let regex = Regex::new("^(.*)z$").unwrap();
+		assert_eq!(regex.captures(&text), None);
+		assert_eq!(regex.captures(&format!("{text}z")).unwrap()[1].as_deref(), Some(text.as_str()));
+
+		// Nested repetition that a backtracking engine takes exponential time to reject
+		let regex = Regex::new("^(a+)+$").unwrap();
+		assert_eq!(regex.captures(&format!("{text}b")), None);
+		assert!(regex.captures(&text).is_some());
+	}
+}
@@ -0,0 +1,590 @@
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::response::StatusCode;
+use crate::uri;
+
+
+// Limits that protect the server from huge requests
+pub const MAX_REQUEST_LINE_SIZE: usize = 8192;
+pub const MAX_HEAD_SIZE: usize = 32768;
+
+const MAX_CHUNK_LINE_SIZE: usize = 4096;
+
+// The largest body worth reading just to keep the connection open
+pub const MAX_DISCARDED_BODY_SIZE: u64 = 1 << 20;
+
+const READ_SIZE: usize = 4096;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Version
+{
+	Http10,
+	Http11,
+}
+
+
+// How the end of the request body is found
+#[derive(Clone, Copy, PartialEq)]
+pub enum BodyLength
+{
+	Length(u64),
+	Chunked,
+}
+
+
+// A parsed request line and header section
+pub struct Request
+{
+	pub method: String,
+	pub path: String,
+	pub query: Option<String>,
+	pub version: Version,
+	pub headers: Headers,
+	pub body_length: BodyLength,
+}
+
+
+// Header fields in the order received, looked up by case-insensitive names
+#[derive(Default)]
+pub struct Headers
+{
+	fields: Vec<(String, String)>,
+}
+
+
+// Why a request couldn't be read
+pub enum Error
+{
+	Closed,
+	Timeout,
+	BadRequest,
+	UriTooLong,
+	HeaderFieldsTooLarge,
+	VersionNotSupported,
+	NotImplemented,
+}
+
+
+impl Error
+{
+	// Get the status code of the response to send, if the connection is still usable
+	pub fn status_code(&self) -> Option<StatusCode>
+	{
+		return match self {
+			Error::Closed => None,
+			Error::Timeout => Some(StatusCode::RequestTimeout),
+			Error::BadRequest => Some(StatusCode::BadRequest),
+			Error::UriTooLong => Some(StatusCode::UriTooLong),
+			Error::HeaderFieldsTooLarge => Some(StatusCode::RequestHeaderFieldsTooLarge),
+			Error::VersionNotSupported => Some(StatusCode::HttpVersionNotSupported),
+			Error::NotImplemented => Some(StatusCode::NotImplemented),
+		};
+	}
+}
+
+
+impl Headers
+{
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &str)>
+	{
+		return self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()));
+	}
+
+
+	// Get the value of a field that must appear only once
+	pub fn get<'a>(&'a self, name: &'a str) -> Option<&'a str>
+	{
+		let mut values = self.get_all(name);
+		return match (values.next(), values.next()) {
+			(Some(value), None) => Some(value),
+			_ => None,
+		};
+	}
+
+
+	// Get every value of a field that may be repeated
+	pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str>
+	{
+		return self.fields.iter()
+			.filter(move |(field_name, _)| field_name.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str());
+	}
+
+
+	// Check whether a comma-separated field contains a token, ignoring case and parameters
+	pub fn has_token(&self, name: &str, token: &str) -> bool
+	{
+		return self.get_all(name)
+			.flat_map(|value| value.split(','))
+			.any(|element| element.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(token));
+	}
+}
+
+
+impl Request
+{
+	// Check whether the client wants to keep the connection open after the response
+	pub fn keep_alive(&self) -> bool
+	{
+		if self.headers.has_token("Connection", "close") {
+			return false;
+		}
+		return self.version == Version::Http11 || self.headers.has_token("Connection", "keep-alive");
+	}
+}
+
+
+// Read from the stream until a whole request head is in the buffer, then parse it
+pub fn read(stream: &mut TcpStream, buffer: &mut Vec<u8>, timeout: Duration) -> Result<Request, Error>
+{
+	let deadline = Instant::now() + timeout;
+	loop {
+		// Parse once the end of the head is buffered
+		if let Some(request) = parse_buffered(buffer)? {
+			let _ = stream.set_read_timeout(Some(timeout));
+			return Ok(request);
+		}
+
+		// Give up on a client that is too slow to send the head, unless it hasn't started
+		let remaining = deadline.saturating_duration_since(Instant::now());
+		let started = has_started(buffer);
+		if remaining.is_zero() || stream.set_read_timeout(Some(remaining)).is_err() {
+			return Err(if started { Error::Timeout } else { Error::Closed });
+		}
+
+		// Read more
+		let length = buffer.len();
+		buffer.resize(length + READ_SIZE, 0);
+		let result = stream.read(&mut buffer[length..]);
+		buffer.truncate(length + *result.as_ref().unwrap_or(&0));
+		match result {
+			Ok(0) => return Err(Error::Closed),
+			Ok(_) => (),
+			Err(error) if started && matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+				return Err(Error::Timeout);
+			},
+			Err(_) => return Err(Error::Closed),
+		}
+	}
+}
+
+
+// Parse and remove a request head from the buffer once it is complete
+pub fn parse_buffered(buffer: &mut Vec<u8>) -> Result<Option<Request>, Error>
+{
+	let Some(head_length) = find_end_of_head(buffer) else {
+		// Give up on a request line or a head that is too long
+		check_limits(buffer)?;
+		return Ok(None);
+	};
+
+	if head_length > MAX_HEAD_SIZE {
+		return Err(Error::HeaderFieldsTooLarge);
+	}
+	let request = parse(&buffer[..head_length]);
+	buffer.drain(..head_length);
+	return request.map(Some);
+}
+
+
+// Check whether the client started sending a request
+pub fn has_started(buffer: &[u8]) -> bool
+{
+	return skip_empty_lines(buffer) < buffer.len();
+}
+
+
+// Find the length of the head including the empty line, accepting bare LF line endings
+fn find_end_of_head(buffer: &[u8]) -> Option<usize>
+{
+	let start = skip_empty_lines(buffer);
+	for i in start..buffer.len() {
+		if buffer[i] != b'\n' {
+			continue;
+		}
+		if i > start && buffer[i - 1] == b'\n' {
+			return Some(i + 1);
+		}
+		if i > start + 1 && buffer[i - 1] == b'\r' && buffer[i - 2] == b'\n' {
+			return Some(i + 1);
+		}
+	}
+	return None;
+}
+
+
+// Skip empty lines that some clients send before the request line
+fn skip_empty_lines(buffer: &[u8]) -> usize
+{
+	let mut start = 0;
+	while start < buffer.len() && (buffer[start] == b'\r' || buffer[start] == b'\n') {
+		start += 1;
+	}
+	return start;
+}
+
+
+fn check_limits(buffer: &[u8]) -> Result<(), Error>
+{
+	let start = skip_empty_lines(buffer);
+	let request_line_length = buffer[start..].iter().position(|byte| *byte == b'\n');
+	if request_line_length.is_none() && buffer.len() - start > MAX_REQUEST_LINE_SIZE {
+		return Err(Error::UriTooLong);
+	}
+	if buffer.len() - start > MAX_HEAD_SIZE {
+		return Err(Error::HeaderFieldsTooLarge);
+	}
+	return Ok(());
+}
+
+
+// Parse a complete request head
+pub fn parse(head: &[u8]) -> Result<Request, Error>
+{
+	let head = &head[skip_empty_lines(head)..];
+	let mut lines = head.split(|byte| *byte == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+	// Parse the request line: method SP request-target SP HTTP-version
+	let request_line = lines.next().ok_or(Error::BadRequest)?;
+	if request_line.len() > MAX_REQUEST_LINE_SIZE {
+		return Err(Error::UriTooLong);
+	}
+	let mut parts = request_line.split(|byte| *byte == b' ');
+	let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+		(Some(method), Some(target), Some(version), None) => (method, target, version),
+		_ => return Err(Error::BadRequest),
+	};
+
+	if method.is_empty() || !method.iter().copied().all(is_token_byte) {
+		return Err(Error::BadRequest);
+	}
+	let method = String::from_utf8_lossy(method).into_owned();
+
+	let version = parse_version(version)?;
+
+	// Parse the request target in origin-form, absolute-form, or asterisk-form
+	if target.is_empty() || !target.iter().all(|byte| byte.is_ascii_graphic() && *byte != b'#') {
+		return Err(Error::BadRequest);
+	}
+	let target = String::from_utf8_lossy(target).into_owned();
+	let path_and_query = if target.starts_with('/') || (target == "*" && method == "OPTIONS") {
+		target.as_str()
+	} else if let Some(rest) = strip_prefix_ignore_case(&target, "http://").or_else(|| strip_prefix_ignore_case(&target, "https://")) {
+		rest.find(['/', '?']).map_or("", |i| &rest[i..])
+	} else {
+		return Err(Error::BadRequest);
+	};
+	let (path, query) = match path_and_query.split_once('?') {
+		Some((path, query)) => (path, Some(String::from(query))),
+		None => (path_and_query, None),
+	};
+	// Remove dot segments and duplicate slashes, rejecting a path that goes above the root
+	let path = match path {
+		"" => String::from("/"),
+		"*" => String::from(path),
+		_ => uri::normalize(path).ok_or(Error::BadRequest)?,
+	};
+
+	// Parse header fields: field-name ":" OWS field-value OWS
+	let mut headers = Headers::default();
+	for line in lines {
+		if line.is_empty() {
+			break;
+		}
+		// Reject obsolete line folding
+		if line[0] == b' ' || line[0] == b'\t' {
+			return Err(Error::BadRequest);
+		}
+		let colon = line.iter().position(|byte| *byte == b':').ok_or(Error::BadRequest)?;
+		let name = &line[..colon];
+		if name.is_empty() || !name.iter().copied().all(is_token_byte) {
+			return Err(Error::BadRequest);
+		}
+		let value = line[colon + 1..].trim_ascii();
+		if value.iter().any(|byte| *byte == b'\r' || *byte == 0 || (byte.is_ascii_control() && *byte != b'\t')) {
+			return Err(Error::BadRequest);
+		}
+		headers.fields.push((String::from_utf8_lossy(name).into_owned(), String::from_utf8_lossy(value).into_owned()));
+	}
+
+	let body_length = validate_headers(&headers, version)?;
+
+	return Ok(Request { method, path, query, version, headers, body_length });
+}
+
+
+// Check the header fields that affect how the request is understood and get the body length
+fn validate_headers(headers: &Headers, version: Version) -> Result<BodyLength, Error>
+{
+	// Require exactly one valid Host in HTTP/1.1
+	let mut hosts = headers.get_all("Host");
+	match (hosts.next(), hosts.next()) {
+		(Some(host), None) if is_valid_host(host) => (),
+		(None, None) if version == Version::Http10 => (),
+		_ => return Err(Error::BadRequest),
+	}
+
+	// Reject ambiguous message framing
+	let transfer_encoding = headers.get_all("Transfer-Encoding").collect::<Vec<_>>();
+	let mut content_lengths = headers.get_all("Content-Length").flat_map(|value| value.split(','));
+	if let Some(first) = content_lengths.next() {
+		let first = first.trim();
+		if content_lengths.any(|other| other.trim() != first) || !transfer_encoding.is_empty() {
+			return Err(Error::BadRequest);
+		}
+		return match first.parse::<u64>() {
+			Ok(length) if first.bytes().all(|byte| byte.is_ascii_digit()) => Ok(BodyLength::Length(length)),
+			_ => Err(Error::BadRequest),
+		};
+	}
+
+	// Only support the chunked transfer coding
+	return match transfer_encoding.as_slice() {
+		[] => Ok(BodyLength::Length(0)),
+		[coding] if coding.eq_ignore_ascii_case("chunked") && version == Version::Http11 => Ok(BodyLength::Chunked),
+		_ => Err(Error::NotImplemented),
+	};
+}
+
+
+fn parse_version(version: &[u8]) -> Result<Version, Error>
+{
+	return match version {
+		b"HTTP/1.1" => Ok(Version::Http11),
+		b"HTTP/1.0" => Ok(Version::Http10),
+		[b'H', b'T', b'T', b'P', b'/', major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+			Err(Error::VersionNotSupported)
+		},
+		_ => Err(Error::BadRequest),
+	};
+}
+
+
+// Check for a tchar as defined by RFC 9110
+fn is_token_byte(byte: u8) -> bool
+{
+	return byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte);
+}
+
+
+fn is_valid_host(host: &str) -> bool
+{
+	return !host.is_empty() && host.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"-._~%!$&'()*+,;=:[]".contains(&byte));
+}
+
+
+fn strip_prefix_ignore_case<'a>(string: &'a str, prefix: &str) -> Option<&'a str>
+{
+	if string.len() >= prefix.len() && string.is_char_boundary(prefix.len()) && string[..prefix.len()].eq_ignore_ascii_case(prefix) {
+		return Some(&string[prefix.len()..]);
+	}
+	return None;
+}
+
+
+// Copy the request body to a writer, starting with the bytes already in the buffer
+pub fn copy_body(stream: &mut impl Read, buffer: &mut Vec<u8>, body_length: BodyLength, writer: &mut impl Write)
+	-> std::io::Result<()>
+{
+	let mut reader = BodyReader { stream, buffer };
+
+	let BodyLength::Length(length) = body_length else {
+		// Copy chunks as they are until the last chunk and the trailer section
+		loop {
+			let line = reader.read_line()?;
+			writer.write_all(&line)?;
+			let size = line.split(|byte| *byte == b';' || *byte == b'\r' || *byte == b'\n').next().unwrap_or(b"");
+			let size = std::str::from_utf8(size).ok().and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+				.ok_or_else(|| invalid_data("invalid chunk size"))?;
+			if size == 0 {
+				break;
+			}
+			reader.copy(size + 2, writer)?;
+		}
+		loop {
+			let line = reader.read_line()?;
+			writer.write_all(&line)?;
+			if line == b"\r\n" || line == b"\n" {
+				return Ok(());
+			}
+		}
+	};
+
+	return reader.copy(length, writer);
+}
+
+
+// Read and drop the request body, unless it is too big to bother
+pub fn discard_body(stream: &mut impl Read, buffer: &mut Vec<u8>, body_length: BodyLength) -> bool
+{
+	if body_length == BodyLength::Length(0) {
+		return true;
+	}
+	if let BodyLength::Length(length) = body_length {
+		if length > MAX_DISCARDED_BODY_SIZE {
+			return false;
+		}
+	}
+	return copy_body(stream, buffer, body_length, &mut Discard { remaining: MAX_DISCARDED_BODY_SIZE }).is_ok();
+}
+
+
+fn invalid_data(message: &str) -> std::io::Error
+{
+	return std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+}
+
+
+// Read a body from the buffer, then from the stream
+struct BodyReader<'a, R: Read>
+{
+	stream: &'a mut R,
+	buffer: &'a mut Vec<u8>,
+}
+
+
+impl<R: Read> BodyReader<'_, R>
+{
+	// Read a line including its line ending
+	fn read_line(&mut self) -> std::io::Result<Vec<u8>>
+	{
+		loop {
+			if let Some(i) = self.buffer.iter().position(|byte| *byte == b'\n') {
+				return Ok(self.buffer.drain(..=i).collect());
+			}
+			if self.buffer.len() > MAX_CHUNK_LINE_SIZE {
+				return Err(invalid_data("chunk line too long"));
+			}
+			let length = self.buffer.len();
+			self.buffer.resize(length + READ_SIZE, 0);
+			let read_length = self.stream.read(&mut self.buffer[length..]);
+			self.buffer.truncate(length + *read_length.as_ref().unwrap_or(&0));
+			if read_length? == 0 {
+				return Err(std::io::ErrorKind::UnexpectedEof.into());
+			}
+		}
+	}
+
+
+	// Copy an exact number of bytes
+	fn copy(&mut self, length: u64, writer: &mut impl Write) -> std::io::Result<()>
+	{
+		let buffered = self.buffer.len().min(usize::try_from(length).unwrap_or(usize::MAX));
+		writer.write_all(&self.buffer[..buffered])?;
+		self.buffer.drain(..buffered);
+
+		let remaining = length - buffered as u64;
+		let copied = std::io::copy(&mut (&mut *self.stream).take(remaining), writer)?;
+		if copied < remaining {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		return Ok(());
+	}
+}
+
+
+// Drop everything written, up to a limit
+struct Discard
+{
+	remaining: u64,
+}
+
+
+impl Write for Discard
+{
+	fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize>
+	{
+		self.remaining = self.remaining.checked_sub(bytes.len() as u64).ok_or_else(|| invalid_data("body too big"))?;
+		return Ok(bytes.len());
+	}
+
+
+	fn flush(&mut self) -> std::io::Result<()>
+	{
+		return Ok(());
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	// Parse a buffer like the servers do, getting 200 for a request, 0 for an incomplete one, or the status of the error
+	fn status(buffer: &[u8]) -> u16
+	{
+		return match super::parse_buffered(&mut buffer.to_vec()) {
+			Ok(Some(_)) => 200,
+			Ok(None) => 0,
+			Err(error) => error.status_code().map_or(0, |status_code| status_code as u16),
+		};
+	}
+
+
+	#[test]
+	fn request_line()
+	{
+		let cases: [(&[u8], u16); 16] = [
+			(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+			(b"\r\n\r\nGET / HTTP/1.1\nHost: a\n\n", 200),
+			(b"GET http://a/b?c HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+			(b"OPTIONS * HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+			(b"GET / HTTP/1.0\r\n\r\n", 200),
+			(b"GET / HTTP/1.1\r\nHost: a\r\n", 0),
+			(b"GET  / HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET / HTTP/1.1 x\r\nHost: a\r\n\r\n", 400),
+			(b"G(T / HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET a HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET /a#b HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET /../a HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET * HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+			(b"GET / HTTP/2.0\r\nHost: a\r\n\r\n", 505),
+			(b"GET / HTTP/1.10\r\nHost: a\r\n\r\n", 400),
+			(b"GET / http/1.1\r\nHost: a\r\n\r\n", 400),
+		];
+		for (head, expected) in cases {
+			assert_eq!(status(head), expected, "{}", String::from_utf8_lossy(head));
+		}
+	}
+
+
+	#[test]
+	fn header_fields()
+	{
+		let cases: [(&[u8], u16); 12] = [
+			(b"GET / HTTP/1.1\r\nHost: a\r\nX-A:  b \r\n\r\n", 200),
+			(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\n", 200),
+			(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n", 200),
+			(b"GET / HTTP/1.1\r\n\r\n", 400),
+			(b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n", 400),
+			(b"GET / HTTP/1.1\r\nHost: a/b\r\n\r\n", 400),
+			(b"GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n", 400),
+			(b"GET / HTTP/1.1\r\nHost: a\r\nX A: b\r\n\r\n", 400),
+			(b"GET / HTTP/1.1\r\nHost: a\r\nNo colon\r\n\r\n", 400),
+			(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n", 400),
+			(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n", 400),
+			(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n", 501),
+		];
+		for (head, expected) in cases {
+			assert_eq!(status(head), expected, "{}", String::from_utf8_lossy(head));
+		}
+	}
+
+
+	#[test]
+	fn limits()
+	{
+		let long_target = format!("GET /{} HTTP/1.1\r\nHost: a\r\n\r\n", "a".repeat(super::MAX_REQUEST_LINE_SIZE));
+		assert_eq!(status(long_target.as_bytes()), 414);
+		assert_eq!(status(&long_target.as_bytes()[..super::MAX_REQUEST_LINE_SIZE + 1]), 414);
+
+		let long_header = format!("GET / HTTP/1.1\r\nHost: a\r\nX-A: {}\r\n\r\n", "a".repeat(super::MAX_HEAD_SIZE));
+		assert_eq!(status(long_header.as_bytes()), 431);
+		assert_eq!(status(&long_header.as_bytes()[..super::MAX_HEAD_SIZE + 1]), 431);
+	}
+}
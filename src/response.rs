@@ -0,0 +1,295 @@
+use std::fs::File;
+use std::io::Write;
+use std::net::TcpStream;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+
+
+// The most to send with one call to sendfile, which can't send more than about 2 GB at once
+#[cfg(target_os = "linux")]
+const MAX_SENDFILE_SIZE: u64 = 1 << 30;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatusCode
+{
+	Ok                          = 200,
+	NoContent                   = 204,
+	PartialContent              = 206,
+	MovedPermanently            = 301,
+	Found                       = 302,
+	SeeOther                    = 303,
+	NotModified                 = 304,
+	TemporaryRedirect           = 307,
+	PermanentRedirect           = 308,
+	BadRequest                  = 400,
+	Forbidden                   = 403,
+	NotFound                    = 404,
+	MethodNotAllowed            = 405,
+	RequestTimeout              = 408,
+	PreconditionFailed          = 412,
+	UriTooLong                  = 414,
+	RangeNotSatisfiable         = 416,
+	RequestHeaderFieldsTooLarge = 431,
+	InternalServerError         = 500,
+	NotImplemented              = 501,
+	BadGateway                  = 502,
+	ServiceUnavailable          = 503,
+	HttpVersionNotSupported     = 505,
+}
+
+
+impl StatusCode
+{
+	pub fn from_u16(code: u16) -> Option<StatusCode>
+	{
+		return match code {
+			200 => Some(StatusCode::Ok),
+			204 => Some(StatusCode::NoContent),
+			206 => Some(StatusCode::PartialContent),
+			301 => Some(StatusCode::MovedPermanently),
+			302 => Some(StatusCode::Found),
+			303 => Some(StatusCode::SeeOther),
+			304 => Some(StatusCode::NotModified),
+			307 => Some(StatusCode::TemporaryRedirect),
+			308 => Some(StatusCode::PermanentRedirect),
+			400 => Some(StatusCode::BadRequest),
+			403 => Some(StatusCode::Forbidden),
+			404 => Some(StatusCode::NotFound),
+			405 => Some(StatusCode::MethodNotAllowed),
+			408 => Some(StatusCode::RequestTimeout),
+			412 => Some(StatusCode::PreconditionFailed),
+			414 => Some(StatusCode::UriTooLong),
+			416 => Some(StatusCode::RangeNotSatisfiable),
+			431 => Some(StatusCode::RequestHeaderFieldsTooLarge),
+			500 => Some(StatusCode::InternalServerError),
+			501 => Some(StatusCode::NotImplemented),
+			502 => Some(StatusCode::BadGateway),
+			503 => Some(StatusCode::ServiceUnavailable),
+			505 => Some(StatusCode::HttpVersionNotSupported),
+			_ => None,
+		};
+	}
+
+
+	pub fn reason(self) -> &'static str
+	{
+		return match self {
+			StatusCode::Ok => "OK",
+			StatusCode::NoContent => "No Content",
+			StatusCode::PartialContent => "Partial Content",
+			StatusCode::MovedPermanently => "Moved Permanently",
+			StatusCode::Found => "Found",
+			StatusCode::SeeOther => "See Other",
+			StatusCode::NotModified => "Not Modified",
+			StatusCode::TemporaryRedirect => "Temporary Redirect",
+			StatusCode::PermanentRedirect => "Permanent Redirect",
+			StatusCode::BadRequest => "Bad Request",
+			StatusCode::Forbidden => "Forbidden",
+			StatusCode::NotFound => "Not Found",
+			StatusCode::MethodNotAllowed => "Method Not Allowed",
+			StatusCode::RequestTimeout => "Request Timeout",
+			StatusCode::PreconditionFailed => "Precondition Failed",
+			StatusCode::UriTooLong => "URI Too Long",
+			StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+			StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+			StatusCode::InternalServerError => "Internal Server Error",
+			StatusCode::NotImplemented => "Not Implemented",
+			StatusCode::BadGateway => "Bad Gateway",
+			StatusCode::ServiceUnavailable => "Service Unavailable",
+			StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+		};
+	}
+
+
+	pub fn is_redirect(self) -> bool
+	{
+		return (300..400).contains(&(self as u16));
+	}
+}
+
+
+// A response that can be written by either the blocking or the non-blocking server
+pub struct Response
+{
+	pub status_code: StatusCode,
+	pub headers: Vec<(String, String)>,
+	pub body: Body,
+}
+
+
+// The content of a response, either in memory or read from a file while it's sent
+pub enum Body
+{
+	Bytes(Vec<u8>),
+	File(File, Vec<Piece>),
+}
+
+
+// Part of a file body, which is either text between parts of a multipart response or an offset and length in the file
+pub enum Piece
+{
+	Text(Vec<u8>),
+	Range(u64, u64),
+}
+
+
+impl Body
+{
+	pub fn len(&self) -> u64
+	{
+		return match self {
+			Body::Bytes(bytes) => bytes.len() as u64,
+			Body::File(_, pieces) => pieces.iter().map(Piece::len).sum(),
+		};
+	}
+}
+
+
+impl Piece
+{
+	pub fn len(&self) -> u64
+	{
+		return match self {
+			Piece::Text(text) => text.len() as u64,
+			Piece::Range(_, length) => *length,
+		};
+	}
+}
+
+
+impl Response
+{
+	// Create a new simple response without any content
+	pub fn simple(status_code: StatusCode) -> Response
+	{
+		return Response { status_code, headers: Vec::new(), body: Body::Bytes(Vec::new()) };
+	}
+
+
+	// Create a new redirect response to an encoded location, with a link for clients that don't follow it
+	pub fn redirect(status_code: StatusCode, location: &str) -> Response
+	{
+		// An encoded location has no quotes or angle brackets to escape
+		let href = location.replace('&', "&amp;");
+		let title = format!("{} {}", status_code as u16, status_code.reason());
+		let page = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n<p><a href=\"{href}\">{href}</a></p>\n</body>\n</html>\n");
+		let response = Response { status_code, headers: Vec::new(), body: Body::Bytes(page.into_bytes()) };
+		return response.with_header("Location", location).with_header("Content-Type", "text/html; charset=utf-8");
+	}
+
+
+	// Create a new response with the given content
+	pub fn content(content_type: &str, content: Vec<u8>) -> Response
+	{
+		let response = Response { status_code: StatusCode::Ok, headers: Vec::new(), body: Body::Bytes(content) };
+		return response.with_header("Content-Type", content_type);
+	}
+
+
+	// Create a new response with the content of a file, using the length from its metadata
+	pub fn file(content_type: &str, file: File, length: u64) -> Response
+	{
+		let response = Response { status_code: StatusCode::Ok, headers: Vec::new(), body: Body::File(file, vec![Piece::Range(0, length)]) };
+		return response.with_header("Content-Type", content_type);
+	}
+
+
+	pub fn with_header(mut self, name: &str, value: &str) -> Response
+	{
+		self.headers.push((String::from(name), String::from(value)));
+		return self;
+	}
+
+
+	pub fn with_headers(mut self, headers: &[(String, String)]) -> Response
+	{
+		self.headers.extend_from_slice(headers);
+		return self;
+	}
+
+
+	// Write the status line and the headers, including the length of the content
+	pub fn head(&self) -> Vec<u8>
+	{
+		let status_code = self.status_code as u16;
+		let mut head = format!("HTTP/1.1 {status_code} {}\r\n", self.status_code.reason());
+		for (name, value) in &self.headers {
+			head.push_str(&format!("{name}: {value}\r\n"));
+		}
+		if status_code != 204 && status_code != 304 {
+			head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+		}
+		head.push_str("\r\n");
+		return head.into_bytes();
+	}
+}
+
+
+// Send a response, or only its head for HEAD requests
+pub fn send(stream: &mut TcpStream, response: &Response, send_body: bool) -> std::io::Result<()>
+{
+	stream.write_all(&response.head())?;
+	if !send_body {
+		return Ok(());
+	}
+	return match &response.body {
+		Body::Bytes(bytes) => stream.write_all(bytes),
+		Body::File(file, pieces) => {
+			for piece in pieces {
+				match piece {
+					Piece::Text(text) => stream.write_all(text)?,
+					Piece::Range(offset, length) => send_file(stream, file, *offset, *length)?,
+				}
+			}
+			Ok(())
+		},
+	};
+}
+
+
+// Send part of a file without copying it through user space
+#[cfg(target_os = "linux")]
+fn send_file(stream: &mut TcpStream, file: &File, mut offset: u64, length: u64) -> std::io::Result<()>
+{
+	let end = offset + length;
+	while offset < end {
+		match write_file(stream, file, offset, end - offset) {
+			Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+			Ok(written) => offset += written as u64,
+			Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(error) => return Err(error),
+		}
+	}
+	return Ok(());
+}
+
+
+// Send part of a file in chunks where sendfile isn't available
+#[cfg(not(target_os = "linux"))]
+fn send_file(stream: &mut TcpStream, mut file: &File, offset: u64, length: u64) -> std::io::Result<()>
+{
+	use std::io::Read;
+	use std::io::Seek;
+
+	file.seek(std::io::SeekFrom::Start(offset))?;
+	let copied = std::io::copy(&mut file.take(length), stream)?;
+	if copied < length {
+		return Err(std::io::ErrorKind::UnexpectedEof.into());
+	}
+	return Ok(());
+}
+
+
+// Write part of a file at an offset to a socket with one call to sendfile, returning how much was written
+#[cfg(target_os = "linux")]
+pub fn write_file(stream: &TcpStream, file: &File, offset: u64, length: u64) -> std::io::Result<usize>
+{
+	let mut offset = offset as libc::off_t;
+	let count = length.min(MAX_SENDFILE_SIZE) as usize;
+	let written = unsafe { libc::sendfile(stream.as_raw_fd(), file.as_raw_fd(), &mut offset, count) };
+	if written < 0 {
+		return Err(std::io::Error::last_os_error());
+	}
+	return Ok(written as usize);
+}
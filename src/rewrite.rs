@@ -0,0 +1,96 @@
+// Rewrite rules from the config, which change the path and query of a request before it's routed
+
+
+use crate::config::Config;
+use crate::config::Rewrite;
+use crate::config::RewriteFlag;
+use crate::request::Request;
+use crate::uri;
+
+
+// How many rules may rewrite one request, more meaning the rules loop
+const MAX_REWRITES: usize = 10;
+
+
+// Apply the rewrite rules in order to a request, describing each rewrite when tracing, and get whether any rule matched,
+// or why the rules failed
+pub fn apply(config: &Config, request: &mut Request, mut trace: Option<&mut Vec<String>>) -> Result<bool, String>
+{
+	let mut seen = Vec::new();
+	let mut index = 0;
+	while let Some(rule) = config.rewrites.get(index) {
+		index += 1;
+		if !conditions_hold(rule, request) {
+			continue;
+		}
+		let Some(captures) = rule.pattern.captures(&request.path) else {
+			continue;
+		};
+
+		// Encode what the target adds, and keep the query unless the target has one
+		let target = uri::encode_location(&substitute(&rule.to, &captures));
+		let (path, query) = match target.split_once('?') {
+			Some((path, query)) => (path, Some(query)),
+			None => (target.as_str(), None),
+		};
+		let Some(path) = uri::normalize(path).filter(|path| path.starts_with('/')) else {
+			return Err(format!("rewrite rule {index} turned {} into '{target}', which isn't a path", request.path));
+		};
+		if let Some(trace) = trace.as_deref_mut() {
+			trace.push(format!("rewrite rule {index} '{}' matched: {} -> {target}", rule.source, request.path));
+		}
+		seen.push(request.path.clone());
+		request.path = path;
+		if let Some(query) = query {
+			request.query = Some(String::from(query)).filter(|query| !query.is_empty());
+		}
+
+		if seen.len() > MAX_REWRITES || (matches!(rule.flag, RewriteFlag::Last) && seen.contains(&request.path)) {
+			return Err(format!("rewrite loop: {} -> {}", seen.join(" -> "), request.path));
+		}
+		match rule.flag {
+			RewriteFlag::Last => index = 0,
+			RewriteFlag::Continue => (),
+			RewriteFlag::Break => break,
+		}
+	}
+	return Ok(!seen.is_empty());
+}
+
+
+// Check the method, header, and query conditions of a rule
+fn conditions_hold(rule: &Rewrite, request: &Request) -> bool
+{
+	if rule.method.as_ref().is_some_and(|method| !method.eq_ignore_ascii_case(&request.method)) {
+		return false;
+	}
+	if let Some((name, pattern)) = &rule.header {
+		if !request.headers.get_all(name).any(|value| pattern.captures(value).is_some()) {
+			return false;
+		}
+	}
+	if let Some(pattern) = &rule.query {
+		if pattern.captures(request.query.as_deref().unwrap_or("")).is_none() {
+			return false;
+		}
+	}
+	return true;
+}
+
+
+// Replace $0 to $9 with the captured groups, where groups that didn't match are empty
+fn substitute(target: &str, captures: &[Option<String>]) -> String
+{
+	let mut result = String::with_capacity(target.len());
+	let mut chars = target.chars().peekable();
+	while let Some(c) = chars.next() {
+		match (c, chars.peek().and_then(|digit| digit.to_digit(10))) {
+			('$', Some(group)) => {
+				chars.next();
+				result.push_str(captures.get(group as usize).and_then(Option::as_deref).unwrap_or(""));
+			},
+			(c, _) => result.push(c),
+		}
+	}
+	return result;
+}
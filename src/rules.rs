@@ -0,0 +1,339 @@
+// Redirect and header rules from _redirects and _headers files in the public directory, in the format of Netlify, which
+// are read again when they change
+
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+
+use crate::config;
+use crate::config::Config;
+use crate::request::Request;
+use crate::response::Response;
+use crate::response::StatusCode;
+use crate::uri;
+
+
+// The rule files, which aren't served themselves
+pub const FILES: [&str; 2] = ["_redirects", "_headers"];
+
+// The rules of the public directory, with the modification times and sizes of the files they were read from
+static LOADED: Mutex<Option<(Stamps, Arc<Rules>)>> = Mutex::new(None);
+
+
+type Stamps = [Option<(SystemTime, u64)>; 2];
+
+
+#[derive(Default)]
+pub struct Rules
+{
+	redirects: Vec<Redirect>,
+	headers: Vec<Headers>,
+}
+
+
+// A line of _redirects, like "/blog/:year/* /posts/:year/:splat 301!"
+struct Redirect
+{
+	from: String,
+	// Query parameters that must be present, with a literal value or a :placeholder
+	query: Vec<(String, String)>,
+	to: String,
+	status: StatusCode,
+	// Whether the rule applies even when a file exists at the path
+	force: bool,
+}
+
+
+// A block of _headers, with a path and the indented headers below it
+struct Headers
+{
+	path: String,
+	headers: Vec<(String, String)>,
+}
+
+
+// What a redirect rule does with a request
+pub enum Action
+{
+	Redirect(StatusCode, String),
+	// Serve another decoded path, with 200 or 404
+	Rewrite(StatusCode, String),
+}
+
+
+// Get the current rules of the public directory, reading the files again when they changed
+pub fn load(config: &Config) -> Arc<Rules>
+{
+	let directory = Path::new(config.public_dir());
+	let stamps = FILES.map(|name| {
+		let metadata = directory.join(name).metadata().ok()?;
+		return Some((metadata.modified().ok()?, metadata.len()));
+	});
+
+	let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+	if let Some((_, rules)) = loaded.as_ref().filter(|(loaded_stamps, _)| *loaded_stamps == stamps) {
+		return Arc::clone(rules);
+	}
+	let rules = Arc::new(read(directory));
+	*loaded = Some((stamps, Arc::clone(&rules)));
+	return rules;
+}
+
+
+// Read both rule files, reporting the lines that can't be used
+fn read(directory: &Path) -> Rules
+{
+	let mut rules = Rules::default();
+	let mut errors = Vec::new();
+	if let Ok(text) = std::fs::read_to_string(directory.join("_redirects")) {
+		rules.redirects = parse_redirects(&text, &mut errors);
+	}
+	if let Ok(text) = std::fs::read_to_string(directory.join("_headers")) {
+		rules.headers = parse_headers(&text, &mut errors);
+	}
+	for error in errors {
+		eprintln!("serve: {error}");
+	}
+	return rules;
+}
+
+
+fn parse_redirects(text: &str, errors: &mut Vec<String>) -> Vec<Redirect>
+{
+	let mut redirects = Vec::new();
+	for (index, line) in text.lines().enumerate() {
+		let line_number = index + 1;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		// The path, then query parameters, the target, an optional status, and conditions
+		let mut tokens = line.split_whitespace().peekable();
+		let from = tokens.next().unwrap_or("");
+		if !from.starts_with('/') {
+			errors.push(format!("_redirects:{line_number}: invalid path '{from}', expected a path like '/blog/*'"));
+			continue;
+		}
+		let mut query = Vec::new();
+		while let Some((name, value)) = tokens.peek().filter(|token| !is_target(token)).and_then(|token| token.split_once('=')) {
+			query.push((String::from(name), String::from(value)));
+			tokens.next();
+		}
+		let Some(to) = tokens.next() else {
+			errors.push(format!("_redirects:{line_number}: missing target for '{from}'"));
+			continue;
+		};
+
+		let (status, force) = match tokens.next() {
+			Some(status) => {
+				let (code, force) = match status.strip_suffix('!') {
+					Some(code) => (code, true),
+					None => (status, false),
+				};
+				match code.parse().ok().and_then(StatusCode::from_u16) {
+					Some(status) if status.is_redirect() || matches!(status, StatusCode::Ok | StatusCode::NotFound) => (status, force),
+					_ => {
+						errors.push(format!("_redirects:{line_number}: invalid status '{status}', expected 200, 301, 302, 303, 307, 308, or 404"));
+						continue;
+					},
+				}
+			},
+			None => (StatusCode::MovedPermanently, false),
+		};
+		if let Some(condition) = tokens.next() {
+			errors.push(format!("_redirects:{line_number}: unsupported condition '{condition}'"));
+			continue;
+		}
+		if !status.is_redirect() && !to.starts_with('/') {
+			errors.push(format!("_redirects:{line_number}: can't rewrite to '{to}', use a [[proxy]] in the config for other servers"));
+			continue;
+		}
+
+		redirects.push(Redirect { from: String::from(from), query, to: String::from(to), status, force });
+	}
+	return redirects;
+}
+
+
+fn parse_headers(text: &str, errors: &mut Vec<String>) -> Vec<Headers>
+{
+	let mut blocks: Vec<Headers> = Vec::new();
+	for (index, line) in text.lines().enumerate() {
+		let line_number = index + 1;
+		if line.trim().is_empty() || line.trim_start().starts_with('#') {
+			continue;
+		}
+
+		// A path starts a block, and indented lines are its headers
+		if !line.starts_with([' ', '\t']) {
+			let path = line.trim();
+			if path.starts_with('/') {
+				blocks.push(Headers { path: String::from(path), headers: Vec::new() });
+			} else {
+				errors.push(format!("_headers:{line_number}: invalid path '{path}', expected a path like '/assets/*'"));
+			}
+			continue;
+		}
+		let Some(block) = blocks.last_mut() else {
+			errors.push(format!("_headers:{line_number}: header before a path"));
+			continue;
+		};
+		match line.trim().split_once(':') {
+			Some((name, value)) if config::is_header_name(name.trim()) && !value.contains(['\r', '\n']) => {
+				block.headers.push((String::from(name.trim()), String::from(value.trim())));
+			},
+			_ => errors.push(format!("_headers:{line_number}: invalid header '{}', expected 'Name: value'", line.trim())),
+		}
+	}
+	return blocks;
+}
+
+
+impl Rules
+{
+	// Find the first redirect rule for a decoded request path, skipping rules that aren't forced when the path would be
+	// served from a file, which is only checked once a rule matches
+	pub fn route(&self, request: &Request, partial_path: &str, file_exists: impl Fn() -> bool) -> Option<Action>
+	{
+		let mut exists = None;
+		for redirect in &self.redirects {
+			let Some(mut captures) = matches(&redirect.from, partial_path) else {
+				continue;
+			};
+			if !matches_query(&redirect.query, request.query.as_deref(), &mut captures) {
+				continue;
+			}
+			let exists = *exists.get_or_insert_with(&file_exists);
+			if exists && !redirect.force {
+				continue;
+			}
+
+			let location = uri::encode_location(&substitute(&redirect.to, &captures));
+			// Keep the query, unless the rule matched its parameters or the target has its own
+			if redirect.status.is_redirect() {
+				return match request.query.as_deref().filter(|_| redirect.query.is_empty() && !location.contains('?')) {
+					Some(query) => Some(Action::Redirect(redirect.status, format!("{location}?{}", uri::encode_location(query)))),
+					None => Some(Action::Redirect(redirect.status, location)),
+				};
+			}
+
+			// Serve a path without its query, or skip a target that isn't a path
+			let path = location.split(['?', '#']).next().unwrap_or("");
+			if let Some(path) = uri::normalize(path).and_then(|path| uri::decode(&path)) {
+				return Some(Action::Rewrite(redirect.status, path));
+			}
+		}
+		return None;
+	}
+
+
+	// Set the headers of every block matching a decoded request path, replacing headers with the same names
+	pub fn apply_headers(&self, partial_path: &str, response: &mut Response)
+	{
+		let headers: Vec<&(String, String)> = self.headers.iter()
+			.filter(|block| matches(&block.path, partial_path).is_some())
+			.flat_map(|block| &block.headers)
+			.collect();
+		response.headers.retain(|(name, _)| !headers.iter().any(|(replaced, _)| replaced.eq_ignore_ascii_case(name)));
+		response.headers.extend(headers.into_iter().cloned());
+	}
+}
+
+
+// Match a decoded path against a pattern with :placeholders for segments and a final * for the rest, getting the
+// encoded values of the placeholders and the splat
+fn matches(pattern: &str, path: &str) -> Option<Vec<(String, String)>>
+{
+	let mut captures = Vec::new();
+	let mut segments = path.trim_end_matches('/').split('/');
+	for part in pattern.trim_end_matches('/').split('/') {
+		if part == "*" {
+			let splat = segments.collect::<Vec<_>>().join("/");
+			captures.push((String::from("splat"), uri::encode_path(&splat)));
+			return Some(captures);
+		}
+		let segment = segments.next()?;
+		match part.strip_prefix(':') {
+			Some(name) if !segment.is_empty() => captures.push((String::from(name), uri::encode(segment))),
+			Some(_) => return None,
+			None if part != segment => return None,
+			None => (),
+		}
+	}
+	if segments.next().is_some() {
+		return None;
+	}
+	return Some(captures);
+}
+
+
+// Check the query parameters a rule needs, adding the values of its placeholders
+fn matches_query(conditions: &[(String, String)], query: Option<&str>, captures: &mut Vec<(String, String)>) -> bool
+{
+	for (name, expected) in conditions {
+		let parameters = query.unwrap_or("").split('&').filter_map(|parameter| parameter.split_once('='));
+		let Some((_, value)) = parameters.clone().find(|(parameter, _)| parameter == name) else {
+			return false;
+		};
+		match expected.strip_prefix(':') {
+			Some(placeholder) => captures.push((String::from(placeholder), String::from(value))),
+			None if value != expected => return false,
+			None => (),
+		}
+	}
+	return true;
+}
+
+
+// Replace the :placeholders of a target with their values, leaving unknown ones as they are
+fn substitute(target: &str, captures: &[(String, String)]) -> String
+{
+	let mut result = String::with_capacity(target.len());
+	let mut rest = target;
+	while let Some(start) = rest.find(':') {
+		result.push_str(&rest[..start]);
+		let after = &rest[start + 1..];
+		let length = after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+		match captures.iter().find(|(name, _)| length > 0 && *name == after[..length]) {
+			Some((_, value)) => result.push_str(value),
+			None => result.push_str(&rest[start..start + 1 + length]),
+		}
+		rest = &after[length..];
+	}
+	result.push_str(rest);
+	return result;
+}
+
+
+// Check whether a token of a redirect rule is its target rather than a query parameter
+fn is_target(token: &str) -> bool
+{
+	return token.starts_with('/') || token.starts_with("http://") || token.starts_with("https://");
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	#[test]
+	fn parse_headers()
+	{
+		let mut errors = Vec::new();
+		let text = "# Security\n/*\n  X-Frame-Options: DENY\n  X-Bad: a\rSet-Cookie: b\n\tBad Name: c\n/assets/*\n  Cache-Control: max-age=60\n  X-Empty:\n  Orphan\n";
+		let blocks = super::parse_headers(text, &mut errors);
+
+		let headers: Vec<(&str, Vec<(&str, &str)>)> = blocks.iter()
+			.map(|block| (block.path.as_str(), block.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()))
+			.collect();
+		assert_eq!(headers, [
+			("/*", vec![("X-Frame-Options", "DENY")]),
+			("/assets/*", vec![("Cache-Control", "max-age=60"), ("X-Empty", "")]),
+		]);
+		assert_eq!(errors.len(), 3, "{errors:?}");
+		assert!(errors[0].starts_with("_headers:4: invalid header"), "{}", errors[0]);
+	}
+}
@@ -0,0 +1,217 @@
+// Keep requests inside the public directory, following symbolic links only as far as the config allows, and away from
+// hidden files
+
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::config::Symlinks;
+use crate::glob;
+use crate::rules;
+
+
+// Editor swap and backup files, which are denied like dotfiles
+const EDITOR_FILES: [&str; 2] = ["*.swp", "*~"];
+
+
+// Where a request path leads
+pub enum Resolved
+{
+	Found(PathBuf),
+	Missing,
+	// The path exists but can't be served, for the given reason
+	Denied(String),
+}
+
+
+// Find the file or directory of a decoded request path in the public directory, treating a path that leads to a denied
+// file through links like a missing one, as a denied request path is
+pub fn resolve(config: &Config, partial_path: &str) -> Resolved
+{
+	let resolved = follow(config, partial_path);
+	if let Resolved::Found(path) = &resolved {
+		let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+		if let Ok(relative) = canonical.strip_prefix(&config.canonical_root) {
+			let target_path: String = relative.components().map(|component| format!("/{}", component.as_os_str().to_string_lossy())).collect();
+			if is_denied(config, &target_path) {
+				return Resolved::Missing;
+			}
+		}
+	}
+	return resolved;
+}
+
+
+// Find the file or directory of a decoded request path, following symbolic links as the config allows
+fn follow(config: &Config, partial_path: &str) -> Resolved
+{
+	let mut path = Path::new(config.public_dir()).to_path_buf();
+	let segments = partial_path.split('/').filter(|segment| !segment.is_empty());
+
+	match config.follow_symlinks {
+		Symlinks::Always => {
+			path.extend(segments);
+			if !path.exists() {
+				return Resolved::Missing;
+			}
+			return Resolved::Found(path);
+		},
+		// Look at every segment, since any directory on the way could be a link
+		Symlinks::Never => {
+			for segment in segments {
+				path.push(segment);
+				match path.symlink_metadata() {
+					Ok(metadata) if metadata.file_type().is_symlink() => {
+						return Resolved::Denied(format!("{} is a symbolic link and follow-symlinks is \"never\"", path.display()));
+					},
+					Ok(_) => (),
+					Err(_) => return Resolved::Missing,
+				}
+			}
+			return Resolved::Found(path);
+		},
+		// Resolve every link, then check where the path ended up
+		Symlinks::WithinRoot => {
+			path.extend(segments);
+			let Ok(canonical) = path.canonicalize() else {
+				return Resolved::Missing;
+			};
+			if !canonical.starts_with(&config.canonical_root) {
+				return Resolved::Denied(format!(
+					"{} leads to {}, which is outside {} and follow-symlinks is \"within-root\"",
+					path.display(),
+					canonical.display(),
+					config.canonical_root.display(),
+				));
+			}
+			return Resolved::Found(canonical);
+		},
+	}
+}
+
+
+// Check whether a decoded request path is denied, because it's a dotfile, in a dot-directory, an editor file, a rule file,
+// or matches a configured glob, and isn't explicitly allowed
+pub fn is_denied(config: &Config, partial_path: &str) -> bool
+{
+	// Allow a directory before it's redirected to the path with a trailing slash
+	let directory_path = format!("{}/", partial_path.trim_end_matches('/'));
+	if config.allow.iter().any(|pattern| glob::matches_path(pattern, partial_path) || glob::matches_path(pattern, &directory_path)) {
+		return false;
+	}
+
+	return partial_path.split('/').any(|segment| segment.starts_with('.'))
+		|| EDITOR_FILES.iter().any(|pattern| glob::matches_path(pattern, partial_path))
+		|| rules::FILES.iter().any(|name| partial_path.strip_prefix('/') == Some(name))
+		|| config.deny.iter().any(|pattern| glob::matches_path(pattern, partial_path));
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	#[cfg(unix)]
+	use super::Resolved;
+	use crate::config::Config;
+	#[cfg(unix)]
+	use crate::config::Symlinks;
+	#[cfg(unix)]
+	use crate::testing::TempDir;
+
+
+	#[cfg(unix)]
+	fn describe(resolved: Resolved) -> &'static str
+	{
+		return match resolved {
+			Resolved::Found(_) => "found",
+			Resolved::Missing => "missing",
+			Resolved::Denied(_) => "denied",
+		};
+	}
+
+
+	#[test]
+	fn is_denied()
+	{
+		let config = Config {
+			deny: vec![String::from("*.bak"), String::from("/private/**")],
+			allow: vec![String::from("/.well-known/**"), String::from("/private/public.txt")],
+			..Config::default()
+		};
+		let cases = [
+			("/", false),
+			("/index.html", false),
+			("/a.md", false),
+			("/v1..2/notes.html", false),
+			("/.env", true),
+			("/.git/config", true),
+			("/assets/.hidden/app.js", true),
+			("/a.md.swp", true),
+			("/notes/a.md~", true),
+			("/_redirects", true),
+			("/_headers", true),
+			("/docs/_redirects", false),
+			("/a.bak", true),
+			("/private/key.pem", true),
+			("/private/public.txt", false),
+			("/.well-known/security.txt", false),
+			// Before the redirect to /.well-known/
+			("/.well-known", false),
+		];
+		for (partial_path, expected) in cases {
+			assert_eq!(super::is_denied(&config, partial_path), expected, "{partial_path}");
+		}
+	}
+
+
+	#[cfg(unix)]
+	#[test]
+	fn links_to_denied_files()
+	{
+		let directory = TempDir::new("links-to-denied-files");
+		directory.file(".git/config", "secret");
+		directory.file("a.md.swp", "swap");
+		directory.file("private/key.pem", "key");
+		directory.file("page.html", "page");
+		directory.link("gitlink", ".git");
+		directory.link("swaplink", "a.md.swp");
+		directory.link("keylink", "private/key.pem");
+		directory.link("pagelink", "page.html");
+		let mut config = directory.config();
+		config.deny = vec![String::from("/private/**")];
+
+		let cases = [("/gitlink/config", "missing"), ("/swaplink", "missing"), ("/keylink", "missing"), ("/pagelink", "found")];
+		for (partial_path, expected) in cases {
+			assert_eq!(describe(super::resolve(&config, partial_path)), expected, "{partial_path}");
+		}
+	}
+
+
+	#[cfg(unix)]
+	#[test]
+	fn symlink_policies()
+	{
+		let outside = TempDir::new("symlink-policies-outside");
+		outside.file("secret.txt", "secret");
+		let directory = TempDir::new("symlink-policies");
+		directory.file("index.html", "index");
+		directory.file("inner/a.txt", "a");
+		directory.link("in", "inner");
+		directory.link("out", &outside.path.to_string_lossy());
+		directory.link("broken", "nothing");
+
+		let cases = [
+			(Symlinks::Always, [("/index.html", "found"), ("/in/a.txt", "found"), ("/out/secret.txt", "found"), ("/broken", "missing"), ("/none", "missing")]),
+			(Symlinks::WithinRoot, [("/index.html", "found"), ("/in/a.txt", "found"), ("/out/secret.txt", "denied"), ("/broken", "missing"), ("/none", "missing")]),
+			(Symlinks::Never, [("/index.html", "found"), ("/in/a.txt", "denied"), ("/out/secret.txt", "denied"), ("/broken", "denied"), ("/none", "missing")]),
+		];
+		for (policy, paths) in cases {
+			let mut config = directory.config();
+			config.follow_symlinks = policy;
+			for (partial_path, expected) in paths {
+				assert_eq!(describe(super::resolve(&config, partial_path)), expected, "{partial_path}");
+			}
+		}
+	}
+}
@@ -0,0 +1,185 @@
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config::Concurrency;
+use crate::config::Config;
+use crate::handler;
+use crate::handler::Route;
+use crate::proxy;
+#[cfg(target_os = "linux")]
+use crate::reactor;
+use crate::request;
+use crate::response;
+use crate::response::StatusCode;
+
+
+// How long to wait for the next request on a persistent connection
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often an idle connection checks whether another connection needs its worker
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+// How long to spend turning away a client when there are too many connections
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+
+// Connections that are open, including the ones waiting for a worker
+#[derive(Default)]
+struct Connections
+{
+	open: AtomicUsize,
+	waiting: AtomicUsize,
+}
+
+
+// Accept connections and handle them with the configured concurrency model
+pub fn run(config: &'static Config, listener: &TcpListener) -> std::io::Result<()>
+{
+	match config.concurrency {
+		Concurrency::Threads => run_threads(config, listener),
+		#[cfg(target_os = "linux")]
+		Concurrency::Epoll => return reactor::run(config, listener),
+	}
+	return Ok(());
+}
+
+
+// Accept connections and hand them to a fixed number of worker threads
+fn run_threads(config: &Config, listener: &TcpListener)
+{
+	let connections = Connections::default();
+	let (sender, receiver) = mpsc::channel();
+	let receiver = Mutex::new(receiver);
+
+	std::thread::scope(|scope| {
+		for _ in 0..config.workers {
+			scope.spawn(|| work(config, &receiver, &connections));
+		}
+
+		for stream in listener.incoming().flatten() {
+			// Turn the client away when there are too many connections
+			if connections.open.load(Ordering::SeqCst) >= config.max_connections {
+				reject(config, stream);
+				continue;
+			}
+
+			connections.open.fetch_add(1, Ordering::SeqCst);
+			connections.waiting.fetch_add(1, Ordering::SeqCst);
+			if sender.send(stream).is_err() {
+				return;
+			}
+		}
+	});
+}
+
+
+// Handle connections from the queue one at a time
+fn work(config: &Config, receiver: &Mutex<Receiver<TcpStream>>, connections: &Connections)
+{
+	let mut buffer = Vec::new();
+
+	loop {
+		let stream = match receiver.lock().map(|receiver| receiver.recv()) {
+			Ok(Ok(stream)) => stream,
+			_ => return,
+		};
+		connections.waiting.fetch_sub(1, Ordering::SeqCst);
+
+		// Keep the worker alive even if handling the connection panics
+		let _ = std::panic::catch_unwind(AssertUnwindSafe(|| handle_stream(config, connections, &mut buffer, stream)));
+
+		connections.open.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+
+// Serve requests on a connection until it closes or goes idle
+fn handle_stream(config: &Config, connections: &Connections, buffer: &mut Vec<u8>, mut stream: TcpStream)
+{
+	let _ = stream.set_write_timeout(Some(config.timeout));
+	// Send the head and the body as they're written, rather than waiting for the client to acknowledge the head
+	let _ = stream.set_nodelay(true);
+
+	buffer.clear();
+	loop {
+		// Handle pipelined requests right away, otherwise wait for the next one
+		if buffer.is_empty() && !wait_for_request(&stream, connections) {
+			return;
+		}
+		if !handle_request(config, buffer, &mut stream) {
+			return;
+		}
+	}
+}
+
+
+// Try to read a request and write a response, then tell whether the connection can be reused
+fn handle_request(config: &Config, buffer: &mut Vec<u8>, stream: &mut TcpStream) -> bool
+{
+	// Read and parse the request head or send an error response
+	let mut request = match request::read(stream, buffer, config.timeout) {
+		Ok(request) => request,
+		Err(error) => {
+			if let Some(status_code) = error.status_code() {
+				let _ = response::send(stream, &handler::error(config, status_code), true);
+			}
+			return false;
+		},
+	};
+
+	// Forward the request to a configured server or create a response
+	let mut response = match handler::route(config, &mut request) {
+		Route::Respond(response) => response,
+		Route::Proxy(proxy) => {
+			proxy::forward(stream, buffer, config, proxy, &request);
+			return false;
+		},
+	};
+
+	// Skip the body so the next request can be read, then tell the client whether the connection stays open
+	let keep_alive = request.keep_alive() && request::discard_body(stream, buffer, request.body_length);
+	handler::set_connection(&mut response, &request, keep_alive);
+
+	return response::send(stream, &response, request.method != "HEAD").is_ok() && keep_alive;
+}
+
+
+// Wait for the next request, giving up when the client is idle or another connection is waiting for a worker
+fn wait_for_request(stream: &TcpStream, connections: &Connections) -> bool
+{
+	if stream.set_read_timeout(Some(IDLE_CHECK_INTERVAL)).is_err() {
+		return false;
+	}
+
+	let start = Instant::now();
+	loop {
+		match stream.peek(&mut [0]) {
+			Ok(length) => return length > 0,
+			Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+				if connections.waiting.load(Ordering::SeqCst) > 0 || start.elapsed() >= IDLE_TIMEOUT {
+					return false;
+				}
+			},
+			Err(_) => return false,
+		}
+	}
+}
+
+
+// Tell a client to try again later
+fn reject(config: &Config, mut stream: TcpStream)
+{
+	let response = handler::error(config, StatusCode::ServiceUnavailable).with_header("Retry-After", "1");
+
+	let _ = stream.set_write_timeout(Some(REJECT_TIMEOUT));
+	let _ = response::send(&mut stream, &response, true);
+}
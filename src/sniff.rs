@@ -0,0 +1,144 @@
+// Content sniffing for files without an extension, following the rules for identifying an unknown MIME type in the
+// WHATWG MIME Sniffing Standard, where the server may sniff scriptable types
+
+
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+
+// How much of a file to look at, which is the size of a resource header in the standard
+const HEADER_SIZE: usize = 1445;
+
+// Tags that start an HTML document when followed by a space or >
+const HTML_TAGS: [&[u8]; 17] = [
+	b"<!DOCTYPE HTML", b"<HTML", b"<HEAD", b"<SCRIPT", b"<IFRAME", b"<H1", b"<DIV", b"<FONT", b"<TABLE", b"<A", b"<STYLE",
+	b"<TITLE", b"<B", b"<BODY", b"<BR", b"<P", b"<!--",
+];
+
+// Signatures with a mask, where 0xFF means the byte must match and 0x00 means any byte
+const SIGNATURES: [(&[u8], &[u8], &str); 21] = [
+	(b"%PDF-", b"\xFF\xFF\xFF\xFF\xFF", "application/pdf"),
+	(b"%!PS-Adobe-", b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF", "application/postscript"),
+	(b"\xFE\xFF", b"\xFF\xFF", "text/plain"),
+	(b"\xFF\xFE", b"\xFF\xFF", "text/plain"),
+	(b"\xEF\xBB\xBF", b"\xFF\xFF\xFF", "text/plain"),
+	(b"\x00\x00\x01\x00", b"\xFF\xFF\xFF\xFF", "image/x-icon"),
+	(b"\x00\x00\x02\x00", b"\xFF\xFF\xFF\xFF", "image/x-icon"),
+	(b"BM", b"\xFF\xFF", "image/bmp"),
+	(b"GIF87a", b"\xFF\xFF\xFF\xFF\xFF\xFF", "image/gif"),
+	(b"GIF89a", b"\xFF\xFF\xFF\xFF\xFF\xFF", "image/gif"),
+	(b"RIFF\x00\x00\x00\x00WEBPVP", b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF", "image/webp"),
+	(b"\x89PNG\r\n\x1A\n", b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF", "image/png"),
+	(b"\xFF\xD8\xFF", b"\xFF\xFF\xFF", "image/jpeg"),
+	(b"FORM\x00\x00\x00\x00AIFF", b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "audio/aiff"),
+	(b"ID3", b"\xFF\xFF\xFF", "audio/mpeg"),
+	(b"OggS\x00", b"\xFF\xFF\xFF\xFF\xFF", "application/ogg"),
+	(b"MThd\x00\x00\x00\x06", b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF", "audio/midi"),
+	(b"RIFF\x00\x00\x00\x00AVI ", b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "video/avi"),
+	(b"RIFF\x00\x00\x00\x00WAVE", b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF", "audio/wave"),
+	(b"\x1F\x8B\x08", b"\xFF\xFF\xFF", "application/x-gzip"),
+	(b"PK\x03\x04", b"\xFF\xFF\xFF\xFF", "application/zip"),
+];
+
+
+// Read the start of a file, leaving its position at the beginning
+pub fn read_header(mut file: &File) -> std::io::Result<Vec<u8>>
+{
+	let mut header = Vec::with_capacity(HEADER_SIZE);
+	file.take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+	file.seek(SeekFrom::Start(0))?;
+	return Ok(header);
+}
+
+
+// Get the charset of a byte order mark at the start of a file
+pub fn bom_charset(header: &[u8]) -> Option<&'static str>
+{
+	return match header {
+		[0xEF, 0xBB, 0xBF, ..] => Some("utf-8"),
+		[0xFE, 0xFF, ..] => Some("utf-16be"),
+		[0xFF, 0xFE, ..] => Some("utf-16le"),
+		_ => None,
+	};
+}
+
+
+// Identify the content type of a file from its first bytes
+pub fn sniff(header: &[u8]) -> &'static str
+{
+	// Markup, after leading whitespace
+	let start = header.iter().position(|byte| !matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')).unwrap_or(header.len());
+	let markup = &header[start..];
+	for tag in HTML_TAGS {
+		if markup.len() > tag.len() && markup[..tag.len()].eq_ignore_ascii_case(tag) && matches!(markup[tag.len()], b' ' | b'>') {
+			return "text/html";
+		}
+	}
+	if markup.starts_with(b"<?xml") {
+		return "text/xml";
+	}
+
+	// Documents, images, audio, video, and archives
+	for (pattern, mask, content_type) in SIGNATURES {
+		if header.len() >= pattern.len() && header.iter().zip(pattern.iter().zip(mask)).all(|(byte, (pattern, mask))| byte & mask == *pattern) {
+			return content_type;
+		}
+	}
+	if is_mp4(header) {
+		return "video/mp4";
+	}
+	if is_webm(header) {
+		return "video/webm";
+	}
+	if header.starts_with(b"Rar!\x1A\x07\x00") || header.starts_with(b"Rar!\x1A\x07\x01\x00") {
+		return "application/x-rar-compressed";
+	}
+
+	// Text unless there are bytes that never appear in text
+	if header.iter().any(|byte| matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)) {
+		return "application/octet-stream";
+	}
+	return "text/plain";
+}
+
+
+// Check for an ISO base media file with an MP4 brand
+fn is_mp4(header: &[u8]) -> bool
+{
+	if header.len() < 12 {
+		return false;
+	}
+	let box_size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+	if header.len() < box_size || !box_size.is_multiple_of(4) || &header[4..8] != b"ftyp" {
+		return false;
+	}
+	if &header[8..11] == b"mp4" {
+		return true;
+	}
+	return (16..box_size).step_by(4).any(|offset| &header[offset..offset + 3] == b"mp4");
+}
+
+
+// Check for a Matroska file with the WebM document type
+fn is_webm(header: &[u8]) -> bool
+{
+	if !header.starts_with(b"\x1A\x45\xDF\xA3") {
+		return false;
+	}
+	for index in 4..header.len().min(38).saturating_sub(1) {
+		if header[index] != 0x42 || header[index + 1] != 0x82 {
+			continue;
+		}
+
+		// Skip the variable-length size of the document type
+		let Some(&first) = header.get(index + 2) else {
+			return false;
+		};
+		let size_length = first.leading_zeros() as usize + 1;
+		let start = index + 2 + size_length;
+		return header.get(start..start + 4) == Some(b"webm");
+	}
+	return false;
+}
@@ -0,0 +1,63 @@
+// Helpers for tests that need a public directory on disk
+
+
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+
+// A directory under the temporary directory, which is removed with everything in it when dropped
+pub struct TempDir
+{
+	pub path: PathBuf,
+}
+
+
+impl TempDir
+{
+	// Create an empty directory, named after the test so tests running at the same time don't share one
+	pub fn new(name: &str) -> TempDir
+	{
+		let path = std::env::temp_dir().join(format!("serve-test-{}-{name}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&path);
+		std::fs::create_dir_all(&path).unwrap();
+		return TempDir { path };
+	}
+
+
+	// Create a file and its parent directories from a path relative to the directory
+	pub fn file(&self, partial_path: &str, content: &str)
+	{
+		let path = self.path.join(partial_path.trim_start_matches('/'));
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		std::fs::write(path, content).unwrap();
+	}
+
+
+	// Create a symbolic link at a path relative to the directory
+	#[cfg(unix)]
+	pub fn link(&self, partial_path: &str, target: &str)
+	{
+		std::os::unix::fs::symlink(target, self.path.join(partial_path.trim_start_matches('/'))).unwrap();
+	}
+
+
+	// Get the default config with the directory as the public directory
+	pub fn config(&self) -> Config
+	{
+		return Config {
+			root: Some(self.path.to_string_lossy().into_owned()),
+			canonical_root: self.path.canonicalize().unwrap(),
+			..Config::default()
+		};
+	}
+}
+
+
+impl Drop for TempDir
+{
+	fn drop(&mut self)
+	{
+		let _ = std::fs::remove_dir_all(&self.path);
+	}
+}
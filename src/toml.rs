@@ -0,0 +1,437 @@
+// A small subset of TOML: tables, arrays of tables, and keys with string, integer, boolean, or array values
+
+
+pub enum Value
+{
+	String(String),
+	Integer(i64),
+	Boolean(bool),
+	Array(Vec<Value>),
+}
+
+
+pub struct Entry
+{
+	pub key: String,
+	pub value: Value,
+	pub line: usize,
+}
+
+
+// A [table] or an element of an [[array]], where the root table has an empty name
+pub struct Table
+{
+	pub name: String,
+	pub array: bool,
+	pub line: usize,
+	pub entries: Vec<Entry>,
+}
+
+
+pub struct Error
+{
+	pub line: usize,
+	pub message: String,
+}
+
+
+impl Value
+{
+	// Name the type for diagnostics
+	pub fn type_name(&self) -> &'static str
+	{
+		return match self {
+			Value::String(_) => "string",
+			Value::Integer(_) => "integer",
+			Value::Boolean(_) => "boolean",
+			Value::Array(_) => "array",
+		};
+	}
+}
+
+
+struct Parser
+{
+	chars: Vec<char>,
+	position: usize,
+	line: usize,
+}
+
+
+// Parse a whole document, collecting every error instead of stopping at the first one
+pub fn parse(text: &str) -> Result<Vec<Table>, Vec<Error>>
+{
+	let mut parser = Parser { chars: text.chars().collect(), position: 0, line: 1 };
+	let mut tables = vec![Table { name: String::new(), array: false, line: 1, entries: Vec::new() }];
+	let mut errors = Vec::new();
+
+	loop {
+		parser.skip_blank_lines();
+		if parser.peek().is_none() {
+			break;
+		}
+
+		let line = parser.line;
+		if let Err(message) = parser.parse_statement(&mut tables) {
+			errors.push(Error { line, message });
+			parser.skip_line();
+			continue;
+		}
+		if let Err(message) = parser.expect_end_of_line() {
+			errors.push(Error { line: parser.line, message });
+			parser.skip_line();
+		}
+	}
+
+	if !errors.is_empty() {
+		return Err(errors);
+	}
+	return Ok(tables);
+}
+
+
+impl Parser
+{
+	fn peek(&self) -> Option<char>
+	{
+		return self.chars.get(self.position).copied();
+	}
+
+
+	fn next(&mut self) -> Option<char>
+	{
+		let c = self.peek()?;
+		self.position += 1;
+		if c == '\n' {
+			self.line += 1;
+		}
+		return Some(c);
+	}
+
+
+	fn skip_spaces(&mut self)
+	{
+		while matches!(self.peek(), Some(' ' | '\t')) {
+			self.next();
+		}
+	}
+
+
+	fn skip_comment(&mut self)
+	{
+		if self.peek() == Some('#') {
+			while !matches!(self.peek(), None | Some('\n')) {
+				self.next();
+			}
+		}
+	}
+
+
+	fn skip_line(&mut self)
+	{
+		while let Some(c) = self.next() {
+			if c == '\n' {
+				break;
+			}
+		}
+	}
+
+
+	fn skip_blank_lines(&mut self)
+	{
+		loop {
+			self.skip_spaces();
+			self.skip_comment();
+			match self.peek() {
+				Some('\n' | '\r') => { self.next(); },
+				_ => break,
+			}
+		}
+	}
+
+
+	fn expect_end_of_line(&mut self) -> Result<(), String>
+	{
+		self.skip_spaces();
+		self.skip_comment();
+		match self.peek() {
+			None => return Ok(()),
+			Some('\r') if self.chars.get(self.position + 1) == Some(&'\n') => {
+				self.next();
+				self.next();
+				return Ok(());
+			},
+			Some('\n') => {
+				self.next();
+				return Ok(());
+			},
+			Some(c) => return Err(format!("unexpected '{c}' after value")),
+		}
+	}
+
+
+	// Parse a table header or a key/value pair
+	fn parse_statement(&mut self, tables: &mut Vec<Table>) -> Result<(), String>
+	{
+		let line = self.line;
+
+		if self.peek() == Some('[') {
+			self.next();
+			let array = self.peek() == Some('[');
+			if array {
+				self.next();
+			}
+			self.skip_spaces();
+			let name = self.parse_key()?;
+			self.skip_spaces();
+			if self.next() != Some(']') || (array && self.next() != Some(']')) {
+				return Err(format!("expected '{}' after table name", if array { "]]" } else { "]" }));
+			}
+			if !array && tables.iter().any(|table| table.name == name) {
+				return Err(format!("duplicate table '{name}'"));
+			}
+			tables.push(Table { name, array, line, entries: Vec::new() });
+			return Ok(());
+		}
+
+		let key = self.parse_key()?;
+		self.skip_spaces();
+		if self.next() != Some('=') {
+			return Err(format!("expected '=' after key '{key}'"));
+		}
+		self.skip_spaces();
+		let value = self.parse_value()?;
+
+		let table = tables.last_mut().unwrap();
+		if table.entries.iter().any(|entry| entry.key == key) {
+			return Err(format!("duplicate key '{key}'"));
+		}
+		table.entries.push(Entry { key, value, line });
+		return Ok(());
+	}
+
+
+	// Parse a bare or quoted key
+	fn parse_key(&mut self) -> Result<String, String>
+	{
+		let key = match self.peek() {
+			Some('"' | '\'') => self.parse_string()?,
+			_ => {
+				let mut key = String::new();
+				while let Some(c) = self.peek() {
+					if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+						break;
+					}
+					key.push(c);
+					self.next();
+				}
+				if key.is_empty() {
+					return Err(match self.peek() {
+						Some(c) if c != '\n' && c != '\r' => format!("expected a key, found '{c}'"),
+						_ => String::from("expected a key"),
+					});
+				}
+				key
+			},
+		};
+
+		self.skip_spaces();
+		if self.peek() == Some('.') {
+			return Err(String::from("dotted keys are not supported"));
+		}
+		return Ok(key);
+	}
+
+
+	fn parse_value(&mut self) -> Result<Value, String>
+	{
+		return match self.peek() {
+			Some('"' | '\'') => Ok(Value::String(self.parse_string()?)),
+			Some(c) if c == '+' || c == '-' || c.is_ascii_digit() => self.parse_integer(),
+			Some(c) if c.is_ascii_alphabetic() => {
+				let mut word = String::new();
+				while let Some(c) = self.peek().filter(char::is_ascii_alphanumeric) {
+					word.push(c);
+					self.next();
+				}
+				match word.as_str() {
+					"true" => Ok(Value::Boolean(true)),
+					"false" => Ok(Value::Boolean(false)),
+					_ => Err(format!("invalid value '{word}', strings must be quoted")),
+				}
+			},
+			Some('[') => self.parse_array(),
+			Some('{') => Err(String::from("inline tables are not supported")),
+			_ => Err(String::from("expected a value")),
+		};
+	}
+
+
+	// Parse an array that may span lines and end with a comma
+	fn parse_array(&mut self) -> Result<Value, String>
+	{
+		self.next();
+		let mut values = Vec::new();
+		loop {
+			self.skip_blank_lines();
+			if self.peek() == Some(']') {
+				self.next();
+				return Ok(Value::Array(values));
+			}
+			values.push(self.parse_value()?);
+			self.skip_blank_lines();
+			match self.next() {
+				Some(',') => (),
+				Some(']') => return Ok(Value::Array(values)),
+				_ => return Err(String::from("expected ',' or ']' in array")),
+			}
+		}
+	}
+
+
+	fn parse_integer(&mut self) -> Result<Value, String>
+	{
+		let mut text = String::new();
+		while let Some(c) = self.peek() {
+			if !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '_' | '.')) {
+				break;
+			}
+			text.push(c);
+			self.next();
+		}
+
+		return match text.replace('_', "").parse::<i64>() {
+			Ok(integer) => Ok(Value::Integer(integer)),
+			Err(_) => Err(format!("invalid integer '{text}'")),
+		};
+	}
+
+
+	// Parse a basic "string" with escapes or a literal 'string'
+	fn parse_string(&mut self) -> Result<String, String>
+	{
+		let quote = self.next().unwrap();
+		let mut string = String::new();
+		loop {
+			// Leave the end of the line for error recovery
+			if matches!(self.peek(), None | Some('\n')) {
+				return Err(String::from("unterminated string"));
+			}
+			match self.next() {
+				Some(c) if c == quote => return Ok(string),
+				Some('\\') if quote == '"' => {
+					let escaped = match self.next() {
+						Some('"') => '"',
+						Some('\\') => '\\',
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some(c @ ('u' | 'U')) => {
+							let length = if c == 'u' { 4 } else { 8 };
+							let mut hex = String::new();
+							for _ in 0..length {
+								hex.extend(self.next());
+							}
+							u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+								.ok_or_else(|| format!("invalid unicode escape '\\{c}{hex}'"))?
+						},
+						Some(c) => return Err(format!("invalid escape '\\{c}'")),
+						None => return Err(String::from("unterminated string")),
+					};
+					string.push(escaped);
+				},
+				Some(c) => string.push(c),
+				None => unreachable!(),
+			}
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::Value;
+
+
+	// Write a value back in TOML, to compare it easily
+	fn describe(value: &Value) -> String
+	{
+		return match value {
+			Value::String(string) => format!("{string:?}"),
+			Value::Integer(integer) => integer.to_string(),
+			Value::Boolean(boolean) => boolean.to_string(),
+			Value::Array(values) => format!("[{}]", values.iter().map(describe).collect::<Vec<_>>().join(", ")),
+		};
+	}
+
+
+	#[test]
+	fn values()
+	{
+		let cases = [
+			("port = 8080", "8080"),
+			("port = -1", "-1"),
+			("gzip = true", "true"),
+			("root = \"public\" # comment", "\"public\""),
+			("root = 'C:\\site'", "\"C:\\\\site\""),
+			("text = \"a\\tb\\\"c\\u00e9\"", "\"a\\tb\\\"cé\""),
+			("list = [1, \"a\", [true],]", "[1, \"a\", [true]]"),
+			("list = [\n\t\"a\", # first\n\t\"b\",\n]", "[\"a\", \"b\"]"),
+			("\"quoted key\" = 1", "1"),
+		];
+		for (text, expected) in cases {
+			let tables = super::parse(text).unwrap_or_else(|errors| panic!("{text}: {}", errors[0].message));
+			assert_eq!(describe(&tables[0].entries[0].value), expected, "{text}");
+		}
+	}
+
+
+	#[test]
+	fn tables()
+	{
+		let text = "dev = true\r\n\n[headers]\nX-A = \"b\"\n\n[[proxy]]\npath = \"/a\"\n[[proxy]]\npath = \"/b\"\n";
+		let Ok(tables) = super::parse(text) else {
+			panic!("tables didn't parse");
+		};
+		let names: Vec<(&str, bool, usize, usize)> = tables.iter()
+			.map(|table| (table.name.as_str(), table.array, table.line, table.entries.len()))
+			.collect();
+		assert_eq!(names, [("", false, 1, 1), ("headers", false, 3, 1), ("proxy", true, 6, 1), ("proxy", true, 8, 1)]);
+		assert_eq!(tables[3].entries[0].line, 9);
+	}
+
+
+	#[test]
+	fn errors()
+	{
+		let cases = [
+			("port = ", "expected a value"),
+			("port 8080", "expected '=' after key 'port'"),
+			("port = 80 80", "unexpected '8' after value"),
+			("port = 99999999999999999999", "invalid integer"),
+			("root = public", "strings must be quoted"),
+			("root = \"public", "unterminated string"),
+			("root = \"\\q\"", "invalid escape"),
+			("a.b = 1", "dotted keys are not supported"),
+			("a = { b = 1 }", "inline tables are not supported"),
+			("a = [1 2]", "expected ',' or ']' in array"),
+			("a = 1\na = 2", "duplicate key 'a'"),
+			("[a]\n[a]", "duplicate table 'a'"),
+			("[a", "expected ']' after table name"),
+			("= 1", "expected a key"),
+		];
+		for (text, expected) in cases {
+			match super::parse(text) {
+				Ok(_) => panic!("{text} parsed"),
+				Err(errors) => assert!(errors[0].message.contains(expected), "{text}: {}", errors[0].message),
+			}
+		}
+
+		// Every error is reported with its line
+		let Err(errors) = super::parse("a = \nb = 1\nc = x\n") else {
+			panic!("errors weren't found");
+		};
+		assert_eq!(errors.iter().map(|error| error.line).collect::<Vec<_>>(), [1, 3]);
+	}
+}
@@ -0,0 +1,169 @@
+// Request paths are normalized while they're still percent-encoded, then decoded to find files
+
+
+// Remove dot segments and empty segments from an encoded path, or get nothing if it goes above the root
+pub fn normalize(path: &str) -> Option<String>
+{
+	let mut segments = Vec::new();
+	let mut trailing_slash = false;
+	for segment in path.split('/').skip(1) {
+		match segment {
+			"" | "." => trailing_slash = true,
+			".." => {
+				segments.pop()?;
+				trailing_slash = true;
+			},
+			_ => {
+				segments.push(segment);
+				trailing_slash = false;
+			},
+		}
+	}
+
+	let mut normalized = String::with_capacity(path.len());
+	for segment in &segments {
+		normalized.push('/');
+		normalized.push_str(segment);
+	}
+	if trailing_slash || segments.is_empty() {
+		normalized.push('/');
+	}
+	return Some(normalized);
+}
+
+
+// Percent-decode a normalized path, or get nothing if it hides a slash, a null byte, or a dot segment, or isn't UTF-8
+pub fn decode(path: &str) -> Option<String>
+{
+	let bytes = path.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut index = 0;
+	while index < bytes.len() {
+		if bytes[index] != b'%' {
+			decoded.push(bytes[index]);
+			index += 1;
+			continue;
+		}
+		let byte = hex(*bytes.get(index + 1)?)? << 4 | hex(*bytes.get(index + 2)?)?;
+		if byte == b'/' || byte == 0 {
+			return None;
+		}
+		decoded.push(byte);
+		index += 3;
+	}
+
+	let decoded = String::from_utf8(decoded).ok()?;
+	if decoded.split('/').any(|segment| segment == "." || segment == "..") {
+		return None;
+	}
+	return Some(decoded);
+}
+
+
+// Percent-encode text for a path segment, keeping only unreserved characters
+pub fn encode(text: &str) -> String
+{
+	let mut encoded = String::with_capacity(text.len());
+	for byte in text.bytes() {
+		if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+			encoded.push(byte as char);
+		} else {
+			encoded.push_str(&format!("%{byte:02X}"));
+		}
+	}
+	return encoded;
+}
+
+
+// Percent-encode every segment of a decoded path
+pub fn encode_path(path: &str) -> String
+{
+	return path.split('/').map(encode).collect::<Vec<_>>().join("/");
+}
+
+
+// Percent-encode the characters that can't appear in a URI, like spaces and non-ASCII characters, keeping delimiters and
+// characters that are already encoded
+pub fn encode_location(text: &str) -> String
+{
+	let bytes = text.as_bytes();
+	let mut encoded = String::with_capacity(text.len());
+	for (index, &byte) in bytes.iter().enumerate() {
+		let is_escape = byte == b'%' && bytes.get(index + 1..index + 3).is_some_and(|digits| digits.iter().all(|digit| hex(*digit).is_some()));
+		if is_escape || byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&byte) {
+			encoded.push(byte as char);
+		} else {
+			encoded.push_str(&format!("%{byte:02X}"));
+		}
+	}
+	return encoded;
+}
+
+
+fn hex(digit: u8) -> Option<u8>
+{
+	return match digit {
+		b'0'..=b'9' => Some(digit - b'0'),
+		b'a'..=b'f' => Some(digit - b'a' + 10),
+		b'A'..=b'F' => Some(digit - b'A' + 10),
+		_ => None,
+	};
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	#[test]
+	fn normalize_and_decode()
+	{
+		let cases = [
+			("/", Some("/")),
+			("/my%20file.html", Some("/my file.html")),
+			("/h%C3%A9llo.txt", Some("/héllo.txt")),
+			("/v1..2/notes.html", Some("/v1..2/notes.html")),
+			("/a/./b//c/", Some("/a/b/c/")),
+			("/a/b/../c", Some("/a/c")),
+			("/a/..", Some("/")),
+			("/..", None),
+			("/a/../../b", None),
+			("/%2e%2e/", None),
+			("/a/%2E%2e", None),
+			("/a/%2e/b", None),
+			("/a%2fb", None),
+			("/a%2Fb", None),
+			("/a%00b", None),
+			("/%FF", None),
+			("/%C3", None),
+			("/a%2", None),
+			("/a%zz", None),
+			("/100%", None),
+		];
+		for (path, expected) in cases {
+			assert_eq!(super::normalize(path).and_then(|path| super::decode(&path)).as_deref(), expected, "{path}");
+		}
+	}
+
+
+	#[test]
+	fn encode()
+	{
+		assert_eq!(super::encode("a b/c?é"), "a%20b%2Fc%3F%C3%A9");
+		assert_eq!(super::encode_path("/my dir/a#b.html"), "/my%20dir/a%23b.html");
+
+		let cases = [
+			("/about", "/about"),
+			("/my file.html?q=a b", "/my%20file.html?q=a%20b"),
+			("/already%20encoded", "/already%20encoded"),
+			("/100%", "/100%25"),
+			("/%zz", "/%25zz"),
+			("/héllo", "/h%C3%A9llo"),
+			("https://example.com/a?b=c&d#e", "https://example.com/a?b=c&d#e"),
+			("/a\r\nSet-Cookie: x", "/a%0D%0ASet-Cookie:%20x"),
+			("/\"<>", "/%22%3C%3E"),
+		];
+		for (text, expected) in cases {
+			assert_eq!(super::encode_location(text), expected, "{text}");
+		}
+	}
+}